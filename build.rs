@@ -1,10 +1,7 @@
 use std::env;
 
 fn main() {
-    match env::var("CARGO_CFG_TARGET_OS").as_ref().map(|x| &**x) {
-        Ok("macos") => {
-            println!("cargo:rustc-link-lib=framework=IOKit");
-        }
-        _ => {}
+    if let Ok("macos") = env::var("CARGO_CFG_TARGET_OS").as_deref() {
+        println!("cargo:rustc-link-lib=framework=IOKit");
     }
 }
@@ -0,0 +1,277 @@
+//! Persistent index of the books stored in the library.
+//!
+//! Parsing every ebook in the library each time a command runs gets slow once the library grows
+//! to a few thousand books. Instead, the metadata of each book is stored in a catalog file inside
+//! the library, which is updated incrementally. Only books whose size or last modified time
+//! changed since they were last indexed are parsed again.
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common;
+use crate::config;
+use crate::format::epub;
+use crate::{Ebook, Error};
+
+/// Path of the catalog file, relative to the library root.
+const CATALOG_PATH: &str = ".libri/catalog.json";
+
+/// Version of the catalog file format. Catalogs written with a different version are rebuilt.
+const CATALOG_VERSION: u32 = 3;
+
+#[derive(Deserialize, Serialize)]
+struct CatalogFile {
+    version: u32,
+    ebooks: Vec<Ebook>,
+}
+
+/// The set of ebooks in the library.
+///
+/// Paths of ebooks returned by the catalog are absolute, but are stored relative to the library
+/// root on disk so the library can be moved or shared between machines.
+#[derive(Debug)]
+pub struct Catalog {
+    library: PathBuf,
+    ebooks: Vec<Ebook>,
+}
+
+impl Catalog {
+    /// Opens the catalog of the given library, re-indexing any books that were added, changed or
+    /// removed since it was last written. The updated catalog is written back to disk.
+    pub fn open(library: &Path) -> Result<Catalog, Error> {
+        let mut catalog = Catalog {
+            library: library.to_path_buf(),
+            ebooks: read(library)?,
+        };
+        if catalog.refresh()? {
+            catalog.save()?;
+        }
+        Ok(catalog)
+    }
+
+    /// Rebuilds the catalog of the given library from scratch by parsing every book in it. The
+    /// new catalog is written to disk.
+    pub fn rebuild(library: &Path) -> Result<Catalog, Error> {
+        let mut catalog = Catalog {
+            library: library.to_path_buf(),
+            ebooks: Vec::new(),
+        };
+        catalog.refresh()?;
+        catalog.save()?;
+        Ok(catalog)
+    }
+
+    /// Returns the ebooks in the catalog.
+    pub fn ebooks(&self) -> &[Ebook] {
+        &self.ebooks
+    }
+
+    /// Consumes the catalog, returning the ebooks it contains.
+    pub fn into_ebooks(self) -> Vec<Ebook> {
+        self.ebooks
+    }
+
+    /// Returns the ebook with the given unique identifier, if it is in the catalog.
+    pub fn find(&self, identifier: &str) -> Option<&Ebook> {
+        self.ebooks.iter().find(|e| e.identifier == identifier)
+    }
+
+    /// Adds an ebook to the catalog, replacing any existing entry stored at the same path.
+    pub fn insert(&mut self, ebook: Ebook) {
+        self.ebooks.retain(|e| e.path != ebook.path);
+        self.ebooks.push(ebook);
+    }
+
+    /// Removes the ebook stored at the given path from the catalog, returning it.
+    pub fn remove(&mut self, path: &Path) -> Option<Ebook> {
+        let index = self.ebooks.iter().position(|e| e.path == path)?;
+        Some(self.ebooks.remove(index))
+    }
+
+    /// Writes the catalog to disk.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = self.library.join(CATALOG_PATH);
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        let ebooks = self
+            .ebooks
+            .iter()
+            .map(|ebook| {
+                let mut ebook = ebook.clone();
+                if let Ok(relative) = ebook.path.strip_prefix(&self.library) {
+                    ebook.path = relative.to_path_buf();
+                }
+                ebook
+            })
+            .collect();
+        let catalog = CatalogFile {
+            version: CATALOG_VERSION,
+            ebooks,
+        };
+
+        // Write to a temporary file first so an interrupted write never leaves a truncated
+        // catalog behind.
+        let temp_path = path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &catalog).map_err(io::Error::from)?;
+        writer.flush()?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    /// Brings the catalog up to date with the files in the library. Returns true if the catalog
+    /// was modified.
+    fn refresh(&mut self) -> Result<bool, Error> {
+        let mut indexed: HashMap<PathBuf, Ebook> = self
+            .ebooks
+            .drain(..)
+            .map(|ebook| (ebook.path.clone(), ebook))
+            .collect();
+        let mut changed = false;
+
+        for path in common::find_ebooks(&self.library)? {
+            let metadata = path.metadata()?;
+            match indexed.remove(&path) {
+                Some(ebook) if is_current(&ebook, &metadata) => self.ebooks.push(ebook),
+                previous => {
+                    // One unreadable book shouldn't make the rest of the library unusable.
+                    let mut ebook = match epub::parse(&path) {
+                        Ok(ebook) => ebook,
+                        Err(e) => {
+                            eprintln!("skipping '{}' -- {}", path.display(), e);
+                            changed |= previous.is_some();
+                            continue;
+                        }
+                    };
+                    if let Some(previous) = previous {
+                        ebook.imported = previous.imported;
+                    }
+                    self.ebooks.push(ebook);
+                    changed = true;
+                }
+            }
+        }
+
+        // Any remaining entries refer to books which no longer exist.
+        Ok(changed || !indexed.is_empty())
+    }
+}
+
+/// Returns true if the indexed ebook matches the size and last modified time of the file on disk.
+fn is_current(ebook: &Ebook, metadata: &fs::Metadata) -> bool {
+    match common::file_info(metadata) {
+        Ok((size, modified)) => size == ebook.size && modified == ebook.modified,
+        Err(_) => false,
+    }
+}
+
+/// Reads the catalog file of the given library. Returns an empty list if the catalog does not
+/// exist or was written by an incompatible version of libri.
+fn read(library: &Path) -> Result<Vec<Ebook>, Error> {
+    let path = library.join(CATALOG_PATH);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let catalog: CatalogFile = match serde_json::from_reader(BufReader::new(File::open(path)?)) {
+        Ok(catalog) => catalog,
+        // A corrupt catalog is not fatal, it will be rebuilt from the library.
+        Err(_) => return Ok(Vec::new()),
+    };
+    if catalog.version != CATALOG_VERSION {
+        return Ok(Vec::new());
+    }
+
+    Ok(catalog
+        .ebooks
+        .into_iter()
+        .map(|mut ebook| {
+            ebook.path = library.join(&ebook.path);
+            ebook
+        })
+        .collect())
+}
+
+pub fn run(config: &config::Config) -> Result<(), Box<dyn StdError>> {
+    let start = Instant::now();
+    let catalog = Catalog::rebuild(&config.library)?;
+    println!(
+        "indexed {}; finished in {:.2}s",
+        catalog.ebooks().len(),
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fixtures::write_epub;
+
+    #[test]
+    fn open_skips_unreadable_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        write_epub(&dir.path().join("a.epub"), "id-a", "Title A", "Author A");
+        fs::write(dir.path().join("broken.epub"), "not an epub").unwrap();
+
+        let catalog = Catalog::open(dir.path()).unwrap();
+        assert_eq!(catalog.ebooks().len(), 1);
+        assert!(catalog.find("id-a").is_some());
+    }
+
+    #[test]
+    fn open_indexes_new_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        write_epub(&dir.path().join("a.epub"), "id-a", "Title A", "Author A");
+
+        let catalog = Catalog::open(dir.path()).unwrap();
+        assert_eq!(catalog.ebooks().len(), 1);
+        assert_eq!(catalog.find("id-a").unwrap().title, "Title A");
+        assert!(dir.path().join(CATALOG_PATH).exists());
+    }
+
+    #[test]
+    fn open_reads_stored_paths_relative_to_library() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        write_epub(&dir.path().join("a.epub"), "id-a", "Title A", "Author A");
+        Catalog::open(dir.path()).unwrap();
+
+        let contents = fs::read_to_string(dir.path().join(CATALOG_PATH)).unwrap();
+        assert!(contents.contains("\"path\":\"a.epub\""));
+
+        let catalog = Catalog::open(dir.path()).unwrap();
+        assert_eq!(catalog.ebooks()[0].path, dir.path().join("a.epub"));
+    }
+
+    #[test]
+    fn open_removes_deleted_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        write_epub(&dir.path().join("a.epub"), "id-a", "Title A", "Author A");
+        write_epub(&dir.path().join("b.epub"), "id-b", "Title B", "Author B");
+        Catalog::open(dir.path()).unwrap();
+
+        fs::remove_file(dir.path().join("a.epub")).unwrap();
+        let catalog = Catalog::open(dir.path()).unwrap();
+        assert_eq!(catalog.ebooks().len(), 1);
+        assert!(catalog.find("id-a").is_none());
+    }
+
+    #[test]
+    fn open_reparses_changed_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("a.epub");
+        write_epub(&path, "id-a", "Title A", "Author A");
+        Catalog::open(dir.path()).unwrap();
+
+        write_epub(&path, "id-a", "A Much Longer Title", "Author A");
+        let catalog = Catalog::open(dir.path()).unwrap();
+        assert_eq!(catalog.find("id-a").unwrap().title, "A Much Longer Title");
+    }
+}
@@ -1,7 +1,7 @@
-use std::error::Error;
-use std::fs::{self, DirEntry};
+use std::fs::{self, DirEntry, Metadata};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&DirEntry)) -> io::Result<()> {
     if dir.is_dir() {
@@ -19,7 +19,7 @@ fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&DirEntry)) -> io::Result<()> {
 }
 
 /// Returns a vector of paths to ebooks read from the given directory (recursively).
-pub fn find_ebooks(search_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+pub fn find_ebooks(search_dir: &Path) -> io::Result<Vec<PathBuf>> {
     let mut ebook_paths: Vec<PathBuf> = Vec::new();
     visit_dirs(search_dir, &mut |entry| {
         if let Some(extension) = entry.path().extension() {
@@ -33,7 +33,7 @@ pub fn find_ebooks(search_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
 
 /// Copies the file at `from` to `to`.
 ///
-/// If `to` already exists, it will be overwritten.
+/// Returns an error of kind `AlreadyExists` if `to` already exists, rather than overwriting it.
 ///
 /// # Examples
 ///
@@ -49,31 +49,101 @@ pub fn find_ebooks(search_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
 /// # }
 /// ```
 pub fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    check_vacant(to)?;
     fs::copy(from, to)?;
     Ok(())
 }
 
+/// Moves the file at `from` to `to`, falling back to copying and removing the original when the
+/// two paths are on different file systems, such as when moving books off a device. Like `copy`,
+/// never overwrites an existing file at `to`.
 pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
-    fs::rename(from, to)?;
+    check_vacant(to)?;
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+fn check_vacant(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("'{}' already exists", path.display()),
+        ));
+    }
     Ok(())
 }
 
+/// Returns the size and last modified time (in seconds since the Unix epoch) of a file.
+pub fn file_info(metadata: &Metadata) -> io::Result<(u64, i64)> {
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    Ok((metadata.len(), modified))
+}
+
 /// Returns a modified path containing only file-system safe characters.
 pub fn sanitize(path: &str) -> String {
     // TODO: Improve the implementation by modifying the path in-place
     let path = path.as_bytes();
     let mut out: Vec<u8> = Vec::with_capacity(path.len());
-    for i in 0..path.len() {
+    for &c in path {
         // TODO: Sanitize all problem characters
-        if path[i] == b':' {
+        if c == b':' {
             out.push(b'_');
         } else {
-            out.push(path[i]);
+            out.push(c);
         }
     }
     String::from_utf8(out).expect("invalid path")
 }
 
+/// Formats a number without a fractional part when it is a whole number, such as `3` rather than
+/// `3.0`.
+pub fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 {
+        format!("{}", number as i64)
+    } else {
+        format!("{}", number)
+    }
+}
+
+/// Formats a file size in bytes using the largest suitable unit, such as `1.4 MB`.
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB"];
+    if size < 1000 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Removes `dir` and each of its parents which are empty, stopping at `root`.
+pub fn remove_empty_dirs(dir: &Path, root: &Path) -> io::Result<()> {
+    let mut dir = dir;
+    while dir != root && dir.starts_with(root) {
+        if fs::read_dir(dir)?.next().is_some() {
+            break;
+        }
+        fs::remove_dir(dir)?;
+        dir = match dir.parent() {
+            Some(parent) => parent,
+            None => break,
+        };
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,14 +151,60 @@ mod tests {
     #[test]
     fn sanitize_clean_path() {
         let path = "/foo/bar.txt";
-        let sanitized_path = sanitize(&path);
+        let sanitized_path = sanitize(path);
         assert_eq!(sanitized_path, path);
     }
 
+    #[test]
+    fn copy_refuses_to_overwrite() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let from = temp.path().join("new.epub");
+        let to = temp.path().join("existing.epub");
+        fs::write(&from, "new").unwrap();
+        fs::write(&to, "existing").unwrap();
+
+        let result = copy(&from, &to);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        let result = move_file(&from, &to);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&to).unwrap(), "existing");
+        assert!(from.exists());
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1_400_000), "1.4 MB");
+        assert_eq!(format_size(2_000_000_000_000), "2000.0 GB");
+    }
+
     #[test]
     fn sanitize_dirty_path() {
         let path = "/foo:bar.txt";
-        let sanitized_path = sanitize(&path);
+        let sanitized_path = sanitize(path);
         assert_eq!(sanitized_path, "/foo_bar.txt");
     }
+
+    #[test]
+    fn remove_empty_dirs_stops_at_root() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let library = dir.path().join("library");
+        fs::create_dir_all(library.join("Author/Title")).unwrap();
+
+        remove_empty_dirs(&library.join("Author/Title"), &library).unwrap();
+        assert!(!library.join("Author").exists());
+        assert!(library.exists());
+    }
+
+    #[test]
+    fn remove_empty_dirs_keeps_non_empty_parents() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let library = dir.path().join("library");
+        fs::create_dir_all(library.join("Author/Title")).unwrap();
+        fs::create_dir_all(library.join("Author/Other Title")).unwrap();
+
+        remove_empty_dirs(&library.join("Author/Title"), &library).unwrap();
+        assert!(!library.join("Author/Title").exists());
+        assert!(library.join("Author/Other Title").exists());
+    }
 }
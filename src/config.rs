@@ -1,53 +1,344 @@
-use std::error::Error;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use configparser::ini::Ini;
 
-#[derive(Debug)]
+use crate::template::Template;
+use crate::Error;
+
+#[derive(Debug, Default)]
 pub struct Config {
     pub library: PathBuf,
+    /// Describes where books are stored inside the library and on devices.
+    pub path_template: Template,
+    /// Settings for individual devices, keyed by the section name identifying the device.
+    pub devices: HashMap<String, DeviceConfig>,
+    /// Friendly names for devices, keyed by their UID.
+    pub device_names: HashMap<String, String>,
+}
+
+impl Config {
+    /// Returns the settings of the device with the given UID, as shown by `libri device list`, or
+    /// of the profile with the given name.
+    pub fn device(&self, name: &str) -> DeviceConfig {
+        self.devices.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns the friendly name given to the device with the given UID.
+    pub fn device_name(&self, uid: &str) -> Option<&str> {
+        self.device_names.get(uid).map(String::as_str)
+    }
+
+    /// Returns the names of the `[device "NAME"]` sections holding settings which can't apply to
+    /// any device, because NAME is neither a UID nor a friendly name. Such sections are usually
+    /// left over from before UIDs were derived from serial numbers.
+    pub fn unmatched_devices(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .devices
+            .iter()
+            .filter(|(name, device)| {
+                !device.is_profile()
+                    && !is_uid(name)
+                    && !self.device_names.values().any(|friendly| friendly == *name)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the profiles describing devices libri doesn't recognize by itself, by name.
+    pub fn profiles(&self) -> impl Iterator<Item = (&str, &DeviceConfig)> {
+        self.devices
+            .iter()
+            .filter(|(_, device)| device.is_profile())
+            .map(|(name, device)| (name.as_str(), device))
+    }
+}
+
+/// Settings for a single device, read from a `[device "NAME"]` section. NAME is either the UID or
+/// friendly name of a device libri recognizes, or the name of a profile describing a device it
+/// doesn't.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceConfig {
+    /// Convert EPUBs to KEPUBs when uploading them to the device.
+    pub kepub: bool,
+    /// Describes where books are stored on the device, replacing the library path template.
+    pub path_template: Option<Template>,
+    /// The USB vendor ID a profile matches. Must be given together with `product_id`.
+    pub vendor_id: Option<u16>,
+    /// The USB product ID a profile matches.
+    pub product_id: Option<u16>,
+    /// The mount point a profile matches.
+    pub mount_path: Option<PathBuf>,
+    /// The volume label a profile matches, which is the name of the directory the volume is
+    /// mounted at (such as `/media/user/LABEL` or `/Volumes/LABEL`).
+    pub volume_label: Option<String>,
+    /// The directory books are stored in on a profile device, relative to its mount point.
+    pub book_dir: String,
+    /// The file extensions of the formats a profile device can read.
+    pub formats: Vec<String>,
+}
+
+impl DeviceConfig {
+    /// Returns true if the section describes a device libri doesn't recognize by itself, rather
+    /// than holding settings for one it does.
+    pub fn is_profile(&self) -> bool {
+        self.vendor_id.is_some() || self.mount_path.is_some() || self.volume_label.is_some()
+    }
+}
+
+/// Returns true if `name` is formatted like the UID of a device, as shown by `libri device list`.
+fn is_uid(name: &str) -> bool {
+    name.len() == 8 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Parses a USB vendor or product ID, given in hexadecimal with an optional `0x` prefix.
+fn parse_usb_id(value: &str) -> Result<u16, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid USB ID '{}'", value))
+}
+
+/// Reads a `[device "NAME"]` section of the configuration.
+fn read_device(config: &Ini, section: &str) -> Result<DeviceConfig, Error> {
+    let invalid =
+        |key: &str, e: String| Error::InvalidConfig(format!("[{}] {}: {}", section, key, e));
+    let usb_id = |key: &str| {
+        config
+            .get(section, key)
+            .map(|value| parse_usb_id(&value).map_err(|e| invalid(key, e)))
+            .transpose()
+    };
+
+    let device = DeviceConfig {
+        kepub: config
+            .getbool(section, "kepub")
+            .map_err(|e| invalid("kepub", e))?
+            .unwrap_or_default(),
+        path_template: config
+            .get(section, "path_template")
+            .map(|template| Template::parse(&template))
+            .transpose()?,
+        vendor_id: usb_id("vendor_id")?,
+        product_id: usb_id("product_id")?,
+        mount_path: config.get(section, "mount_path").map(PathBuf::from),
+        volume_label: config.get(section, "volume_label"),
+        book_dir: config.get(section, "book_dir").unwrap_or_default(),
+        formats: match config.get(section, "formats") {
+            Some(formats) => formats
+                .split(',')
+                .map(|format| format.trim().trim_start_matches('.').to_lowercase())
+                .filter(|format| !format.is_empty())
+                .collect(),
+            None => vec!["epub".to_owned()],
+        },
+    };
+    if device.vendor_id.is_some() != device.product_id.is_some() {
+        return Err(invalid(
+            "vendor_id",
+            "vendor_id and product_id must be given together".to_owned(),
+        ));
+    }
+    // Profiles describe generic mass storage devices, which can't read KEPUBs.
+    if device.kepub && device.is_profile() {
+        return Err(invalid(
+            "kepub",
+            "only Kobo eReaders can read KEPUBs".to_owned(),
+        ));
+    }
+    Ok(device)
 }
 
-fn default_library() -> String {
+/// Reads the `[device "NAME"]` sections of the configuration.
+fn read_devices(config: &Ini) -> Result<HashMap<String, DeviceConfig>, Error> {
+    let mut devices = HashMap::new();
+    for section in config.sections() {
+        if let Some(name) = section
+            .strip_prefix("device \"")
+            .and_then(|s| s.strip_suffix('"'))
+        {
+            devices.insert(name.to_owned(), read_device(config, &section)?);
+        }
+    }
+    Ok(devices)
+}
+
+/// Reads the `[device-names]` section of the configuration, which maps device UIDs to friendly
+/// names.
+fn read_device_names(config: &Ini) -> HashMap<String, String> {
+    config
+        .get_map_ref()
+        .get("device-names")
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|(uid, name)| Some((uid.to_lowercase(), name.clone()?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Lowercases the keys of the configuration and the names of its sections, except for the names
+/// given to devices in `[device "NAME"]` sections.
+fn normalize(config: &mut Ini) {
+    let map = config.get_mut_map();
+    for (section, keys) in std::mem::take(map) {
+        let section = match section.get(..8) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("device \"") => {
+                format!("device \"{}", &section[8..])
+            }
+            _ => section.to_lowercase(),
+        };
+        let entry = map.entry(section).or_default();
+        for (key, value) in keys {
+            entry.insert(key.to_lowercase(), value);
+        }
+    }
+}
+
+fn home_dir() -> Result<String, Error> {
     // TODO: Make platform specific
-    let home = std::env::var("HOME").unwrap();
-    format!("{}/Documents/books/", home)
+    std::env::var("HOME")
+        .map_err(|_| Error::InvalidConfig("the HOME environment variable is not set".to_owned()))
+}
+
+fn default_library() -> Result<String, Error> {
+    Ok(format!("{}/Documents/books/", home_dir()?))
 }
 
-fn config_path(config_dir: Option<&Path>) -> PathBuf {
+fn config_path(config_dir: Option<&Path>) -> Result<PathBuf, Error> {
     // TODO: Make platform specific
     // TODO: Prefer reading config path from environment variable if present (LIBRI_CONFIG_PATH)
-    let home = std::env::var("HOME").unwrap();
     let mut config_dir = match config_dir {
         Some(dir) => {
-            assert!(dir.is_dir());
+            if !dir.is_dir() {
+                return Err(Error::InvalidConfig(format!(
+                    "configuration directory '{}' does not exist",
+                    dir.display()
+                )));
+            }
             PathBuf::from(dir)
         }
-        None => PathBuf::from(format!("{}/.config/libri/", home)),
+        None => PathBuf::from(format!("{}/.config/libri/", home_dir()?)),
     };
     config_dir.push("config.ini");
-    config_dir
+    Ok(config_dir)
 }
 
 /// Reads the configuration from disk and returns it as a struct.
-pub fn read(config_dir: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+pub fn read(config_dir: Option<&Path>) -> Result<Config, Error> {
     // For now, always look in ~/.config/libri/config.ini. Should migrate to platform specific
     // paths (https://github.com/dirs-dev/directories-rs).
-    let mut config = Ini::new();
-    let config_path = config_path(config_dir);
+    // Device section names are case sensitive, since they include the names of devices and
+    // profiles, so the rest of the configuration is normalized once loaded.
+    let mut config = Ini::new_cs();
+    let config_path = config_path(config_dir)?;
     if config_path.exists() {
-        match config.load(config_path) {
-            Ok(_) => {}
-            Err(error) => panic!("problem reading the configuration file: {}", error),
-        }
+        config.load(&config_path).map_err(|error| {
+            Error::InvalidConfig(format!(
+                "problem reading '{}': {}",
+                config_path.display(),
+                error
+            ))
+        })?;
+        normalize(&mut config);
     }
-    let library = config.get("default", "library");
+    let library = match config.get("default", "library") {
+        Some(library) => library,
+        None => default_library()?,
+    };
+
+    let path_template = match config.get("default", "path_template") {
+        Some(template) => Template::parse(&template)?,
+        None => Template::default(),
+    };
 
-    Ok(Config {
-        library: PathBuf::from(library.unwrap_or_else(default_library)),
-    })
+    let config = Config {
+        library: PathBuf::from(library),
+        path_template,
+        devices: read_devices(&config)?,
+        device_names: read_device_names(&config),
+    };
+    Ok(config)
 }
 
 pub fn run(config: &Config) {
     println!("{:?}", config);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn read_device_sections() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("config.ini"),
+            "Library = /books\n\n[device \"8f02b4d1\"]\nkepub = true\n\n\
+             [Device \"Office Kobo\"]\nKEPUB = true\n\n\
+             [device \"8755\"]\nkepub = true\n\n\
+             [Device-Names]\n3FA2C91E = Office Kobo\n",
+        )
+        .unwrap();
+
+        let config = read(Some(dir.path())).unwrap();
+        assert_eq!(config.library, Path::new("/books"));
+        assert!(config.device("8f02b4d1").kepub);
+        assert!(config.device("Office Kobo").kepub);
+        assert!(!config.device("1234abcd").kepub);
+        assert_eq!(config.profiles().count(), 0);
+        assert_eq!(config.device_name("3fa2c91e"), Some("Office Kobo"));
+        assert_eq!(config.device_name("8f02b4d1"), None);
+        // Numeric UIDs from older versions no longer match any device.
+        assert_eq!(config.unmatched_devices(), ["8755"]);
+    }
+
+    #[test]
+    fn read_device_profile() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("config.ini"),
+            "[device \"My Reader\"]\nvendor_id = 0x1d6b\nproduct_id = 0104\n\
+             book_dir = ebooks\nformats = epub, .PDF\npath_template = {title}.{ext}\n",
+        )
+        .unwrap();
+
+        let config = read(Some(dir.path())).unwrap();
+        let profiles: Vec<_> = config.profiles().collect();
+        assert_eq!(profiles.len(), 1);
+        let (name, profile) = profiles[0];
+        assert_eq!(name, "My Reader");
+        assert_eq!(
+            (profile.vendor_id, profile.product_id),
+            (Some(0x1d6b), Some(0x0104))
+        );
+        assert_eq!(profile.book_dir, "ebooks");
+        assert_eq!(profile.formats, ["epub", "pdf"]);
+        assert_eq!(
+            profile.path_template.as_ref().unwrap().to_string(),
+            "{title}.{ext}"
+        );
+    }
+
+    #[test]
+    fn read_invalid_device_profile() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        for profile in [
+            "vendor_id = 0x1d6b\n",
+            "vendor_id = 0x1d6b\nproduct_id = 0x0104\nkepub = true\n",
+        ] {
+            fs::write(
+                dir.path().join("config.ini"),
+                format!("[device \"my-reader\"]\n{}", profile),
+            )
+            .unwrap();
+            assert!(matches!(
+                read(Some(dir.path())),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+}
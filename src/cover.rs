@@ -0,0 +1,150 @@
+use std::error::Error as StdError;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::catalog::Catalog;
+use crate::common;
+use crate::config;
+use crate::format::epub;
+use crate::{Ebook, Error};
+
+/// Extracts the cover of the ebook at `path` into its directory as `cover.<ext>`, returning the
+/// path of the extracted cover. Covers are only extracted for books which have a directory to
+/// themselves, and an existing cover is never overwritten.
+pub(crate) fn save(path: &Path) -> Result<Option<PathBuf>, Error> {
+    let dir = path.parent().unwrap();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?.path();
+        if entry != path
+            && entry
+                .extension()
+                .is_some_and(|extension| extension == "epub")
+        {
+            return Ok(None);
+        }
+        if entry.file_stem().is_some_and(|stem| stem == "cover") {
+            return Ok(None);
+        }
+    }
+
+    let cover = match epub::cover(path)? {
+        Some(cover) => cover,
+        None => return Ok(None),
+    };
+    let destination = dir.join(format!("cover.{}", cover.extension));
+    fs::write(&destination, cover.data)?;
+    Ok(Some(destination))
+}
+
+/// Returns the ebook matching `book`, which is either the identifier of the ebook or part of its
+/// title.
+fn find<'a>(ebooks: &'a [Ebook], book: &str) -> Result<&'a Ebook, Box<dyn StdError>> {
+    if let Some(ebook) = ebooks.iter().find(|ebook| ebook.identifier == book) {
+        return Ok(ebook);
+    }
+
+    let book_lower = book.to_lowercase();
+    let matches: Vec<&Ebook> = ebooks
+        .iter()
+        .filter(|ebook| ebook.title.to_lowercase().contains(&book_lower))
+        .collect();
+    match matches.as_slice() {
+        [ebook] => Ok(ebook),
+        [] => Err(format!("no book matches '{}'", book).into()),
+        _ => {
+            let titles: Vec<String> = matches
+                .iter()
+                .map(|ebook| format!("  \"{}\" ({})", ebook.title, ebook.identifier))
+                .collect();
+            Err(format!(
+                "'{}' matches {} books:\n{}",
+                book,
+                matches.len(),
+                titles.join("\n")
+            )
+            .into())
+        }
+    }
+}
+
+/// Exports the cover of a book in the library to `output`. When `output` is a directory or
+/// missing, the cover is named after the title of the book. Existing files are never overwritten.
+pub fn run(
+    config: &config::Config,
+    book: &str,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn StdError>> {
+    let catalog = Catalog::open(&config.library)?;
+    let ebook = find(catalog.ebooks(), book)?;
+    let cover = epub::cover(&ebook.path)?
+        .ok_or_else(|| format!("\"{}\" does not have a cover", ebook.title))?;
+
+    let file_name = format!(
+        "{}.{}",
+        common::sanitize(&ebook.title.replace(['/', '\\'], "_")),
+        cover.extension
+    );
+    let destination = match output {
+        Some(output) if output.is_dir() => output.join(file_name),
+        Some(output) => output.to_path_buf(),
+        None => PathBuf::from(file_name),
+    };
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&destination)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(format!("'{}' already exists", destination.display()).into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    file.write_all(&cover.data)?;
+    println!(
+        "saved cover of \"{}\" to '{}'",
+        ebook.title,
+        destination.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{write_epub, write_epub_with_files};
+
+    const OPF: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"
+         unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">1234</dc:identifier>
+    <dc:title>A Title</dc:title>
+    <dc:creator>An Author</dc:creator>
+  </metadata>
+  <manifest>
+    <item id="c" href="cover.jpg" media-type="image/jpeg" properties="cover-image"/>
+  </manifest>
+</package>"#;
+
+    #[test]
+    fn save_cover() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_files(&path, OPF, &[("OEBPS/cover.jpg", b"jpeg")]);
+
+        assert_eq!(save(&path).unwrap(), Some(dir.path().join("cover.jpg")));
+        assert_eq!(fs::read(dir.path().join("cover.jpg")).unwrap(), b"jpeg");
+    }
+
+    #[test]
+    fn save_cover_skips_shared_directories() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_files(&path, OPF, &[("OEBPS/cover.jpg", b"jpeg")]);
+        write_epub(&dir.path().join("other.epub"), "5678", "Other", "An Author");
+
+        assert_eq!(save(&path).unwrap(), None);
+        assert!(!dir.path().join("cover.jpg").exists());
+    }
+}
@@ -1,29 +1,81 @@
+pub mod annotations;
+pub mod books;
+mod capacity;
 mod darwin;
-mod kobo;
+pub mod eject;
+pub(crate) mod kobo;
+mod linux;
 pub mod list;
+mod manifest;
+mod mass_storage;
+pub mod pull;
+pub mod registry;
+pub mod sync;
 mod usb;
 
-use std::error::Error;
+use std::ffi::OsStr;
 use std::io;
 use std::path::{Path, PathBuf};
 
-use super::Ebook;
-use kobo::{Libra2, KOBO_VENDOR_ID, LIBRA_2_PRODUCT_ID};
+use super::config::{Config, DeviceConfig};
+use super::template::Template;
+use super::{Ebook, Error};
+use annotations::ReadingState;
+pub use capacity::Capacity;
+use kobo::Kobo;
+pub use manifest::{Entry, Manifest};
+use mass_storage::MassStorage;
+use registry::Family;
 use usb::UsbDevice;
 
 #[derive(Debug)]
 pub struct Device {
+    uid: String,
     name: String,
     manufacturer: String,
     usb_info: Box<dyn UsbDevice>,
+    /// The USB serial number of the device, if it reports one.
+    serial: Option<String>,
+    /// The name of the profile in the configuration the device was recognized by.
+    profile: Option<String>,
+    /// The friendly name given to the device in the configuration.
+    friendly_name: Option<String>,
+}
+
+/// Hashes `bytes` with 32-bit FNV-1a, which is simple and stable across platforms and releases.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+    })
+}
+
+/// Derives the UID of a device from its USB IDs and serial number. Devices without a serial
+/// number share a UID with other devices of the same model.
+fn uid(vendor_id: u16, product_id: u16, serial: Option<&str>) -> String {
+    let key = format!(
+        "{:04x}:{:04x}:{}",
+        vendor_id,
+        product_id,
+        serial.unwrap_or_default()
+    );
+    format!("{:08x}", fnv1a(key.as_bytes()))
 }
 
 impl Device {
-    pub fn new(name: String, manufacturer: String, usb_info: Box<dyn UsbDevice>) -> Device {
+    pub fn new(
+        name: String,
+        manufacturer: String,
+        usb_info: Box<dyn UsbDevice>,
+        serial: Option<&str>,
+    ) -> Device {
         Device {
+            uid: uid(usb_info.vendor_id(), usb_info.product_id(), serial),
             name,
             manufacturer,
             usb_info,
+            serial: serial.map(str::to_owned),
+            profile: None,
+            friendly_name: None,
         }
     }
 
@@ -31,20 +83,111 @@ impl Device {
         &self.name
     }
 
+    /// Returns the friendly name given to the device in the `[device-names]` section of the
+    /// configuration.
+    pub fn friendly_name(&self) -> Option<&str> {
+        self.friendly_name.as_deref()
+    }
+
     pub fn manufacturer(&self) -> &str {
         &self.manufacturer
     }
 
+    /// Returns an identifier for the device which is short enough to type. It is derived from the
+    /// USB serial number, so it stays the same across connections and differs between two devices
+    /// of the same model.
+    pub fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    /// Returns the USB serial number of the device, if it reports one.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Returns the name of the profile in the configuration the device was recognized by.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Returns true if `id` is the UID or the friendly name of the device.
+    fn is_identified_by(&self, id: &str) -> bool {
+        self.uid == id || self.friendly_name.as_deref() == Some(id)
+    }
+
+    /// Returns the directory where the device is mounted.
+    pub fn mount_dir(&self) -> &Path {
+        self.usb_info.mount_dir()
+    }
+
+    /// Returns the total and free space of the device.
+    pub fn capacity(&self) -> io::Result<Capacity> {
+        capacity::capacity(self.mount_dir())
+    }
+
+    /// Returns the USB vendor ID of the device.
+    pub fn vendor_id(&self) -> u16 {
+        self.usb_info.vendor_id()
+    }
+
+    /// Returns the USB product ID of the device.
+    pub fn product_id(&self) -> u16 {
+        self.usb_info.product_id()
+    }
+
+    /// Returns the settings of the device, read from the profile it was recognized by or from the
+    /// section named after its friendly name or its UID.
+    pub fn settings(&self, config: &Config) -> DeviceConfig {
+        let section = self.profile.as_deref().unwrap_or_else(|| {
+            self.friendly_name
+                .as_deref()
+                .filter(|name| config.devices.contains_key(*name))
+                .unwrap_or(&self.uid)
+        });
+        config.device(section)
+    }
+
+    /// Returns the path on the device an ebook is uploaded to, as described by the configured path
+    /// template.
+    pub fn destination(&self, ebook: &Ebook, config: &Config) -> Result<PathBuf, io::Error> {
+        let settings = self.settings(config);
+        self.usb_info
+            .destination(ebook, path_template(&settings, config), &settings)
+    }
+
+    /// Uploads an ebook to the location on the device described by the configured path template,
+    /// returning the path of the uploaded book.
     pub fn upload_ebook(
         &self,
         ebook: &Ebook,
-        library: &Path,
+        config: &Config,
         dry_run: bool,
-    ) -> Result<(), io::Error> {
-        self.usb_info.upload_ebook(&ebook, &library, dry_run)
+    ) -> Result<PathBuf, io::Error> {
+        let settings = self.settings(config);
+        self.usb_info
+            .upload_ebook(ebook, path_template(&settings, config), &settings, dry_run)
+    }
+
+    /// Returns the reading progress and annotations of the books on the device.
+    pub fn reading_states(&self) -> Result<Vec<ReadingState>, Error> {
+        self.usb_info.reading_states()
+    }
+
+    /// Reads the manifest of the books libri has placed on the device.
+    pub fn manifest(&self) -> Result<Manifest, Error> {
+        Manifest::open(self.mount_dir())
     }
 }
 
+/// Returns the path template used for a device, which is the library template unless the device
+/// settings replace it.
+fn path_template<'a>(settings: &'a DeviceConfig, config: &'a Config) -> &'a Template {
+    settings
+        .path_template
+        .as_ref()
+        .unwrap_or(&config.path_template)
+}
+
 /// Low-level information about a mounted USB device. Other modules should use the specific device
 /// struct which implements the UsbDevice trait to interact with eReaders.
 #[derive(Debug, Default)]
@@ -54,59 +197,181 @@ pub struct MountedDevice {
     name: String,
     vendor_id: u16,
     product_id: u16,
+    /// The USB serial number of the device, if it reports one.
+    serial: Option<String>,
+}
+
+impl MountedDevice {
+    /// Returns the total and free space of the file system the device is mounted at.
+    pub fn capacity(&self) -> io::Result<Capacity> {
+        capacity::capacity(&self.mount_point)
+    }
 }
 
 /// Returns a list of mounted devices (macOS specific).
 #[cfg(target_os = "macos")]
-fn mounted_devices() -> Result<Vec<MountedDevice>, Box<dyn Error>> {
+fn mounted_devices() -> Result<Vec<MountedDevice>, Error> {
     darwin::usb_devices()
 }
 
 /// Returns a list of mounted devices (Linux specific).
 #[cfg(target_os = "linux")]
-fn mounted_devices() -> Result<Vec<MountedDevice>, Box<dyn Error>> {
-    // TODO: Implement
-    panic!("device recognition not yet implemented for Linux");
+fn mounted_devices() -> Result<Vec<MountedDevice>, Error> {
+    linux::usb_devices()
 }
 
 /// Returns a list of mounted devices (Windows specific).
 #[cfg(target_os = "windows")]
-fn mounted_devices() -> Result<Vec<MountedDevice>, Box<dyn Error>> {
+fn mounted_devices() -> Result<Vec<MountedDevice>, Error> {
     // TODO: Implement
-    panic!("device recognition not yet implemented for Windows");
+    Err(Error::Device(
+        "device recognition not yet implemented for Windows".to_owned(),
+    ))
 }
 
 // TODO: Add support for other OS's (the BSDs)
 
-/// Filters the list of mounted devices and returns a list of supported eReaders.
-fn filter(devices: Vec<MountedDevice>) -> Vec<Device> {
+/// Returns true if the mounted device matches every criterion given by the profile.
+fn matches_profile(device: &MountedDevice, profile: &DeviceConfig) -> bool {
+    if let (Some(vendor_id), Some(product_id)) = (profile.vendor_id, profile.product_id) {
+        if (device.vendor_id, device.product_id) != (vendor_id, product_id) {
+            return false;
+        }
+    }
+    if let Some(mount_path) = &profile.mount_path {
+        if device.mount_point != *mount_path {
+            return false;
+        }
+    }
+    if let Some(volume_label) = &profile.volume_label {
+        if device.mount_point.file_name() != Some(OsStr::new(volume_label)) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Filters the list of mounted devices and returns a list of supported eReaders. Devices matching
+/// a profile in the configuration take precedence over the built-in models.
+fn filter(devices: Vec<MountedDevice>, config: &Config) -> Vec<Device> {
+    let mut profiles: Vec<(&str, &DeviceConfig)> = config.profiles().collect();
+    profiles.sort_by_key(|(name, _)| *name);
+
     let mut available_devices: Vec<Device> = Vec::new();
     for device in devices {
-        match (device.vendor_id, device.product_id) {
-            (KOBO_VENDOR_ID, LIBRA_2_PRODUCT_ID) => {
-                available_devices.push(Device::new(
-                    device.name,
-                    device.manufacturer,
-                    Box::new(Libra2::new(device.mount_point)),
-                ));
-            }
-            _ => {}
+        if let Some((name, profile)) = profiles
+            .iter()
+            .find(|(_, profile)| matches_profile(&device, profile))
+        {
+            let usb_info = MassStorage::new(
+                device.mount_point,
+                device.vendor_id,
+                device.product_id,
+                &profile.book_dir,
+                &profile.formats,
+            );
+            let mut available = Device::new(
+                name.to_string(),
+                device.manufacturer,
+                Box::new(usb_info),
+                device.serial.as_deref(),
+            );
+            available.profile = Some(name.to_string());
+            available_devices.push(available);
+            continue;
         }
+
+        let model = match registry::find(device.vendor_id, device.product_id) {
+            Some(model) => model,
+            None => continue,
+        };
+        let usb_info: Box<dyn UsbDevice> = match model.family {
+            Family::Kobo => Box::new(Kobo::new(device.mount_point, model)),
+            Family::MassStorage => Box::new(MassStorage::from_model(device.mount_point, model)),
+        };
+        // Prefer the name reported by the device, since models sharing IDs report their own.
+        let name = if device.name.is_empty() {
+            model.name.to_owned()
+        } else {
+            device.name
+        };
+        available_devices.push(Device::new(
+            name,
+            device.manufacturer,
+            usb_info,
+            device.serial.as_deref(),
+        ));
+    }
+    for device in &mut available_devices {
+        device.friendly_name = config.device_name(&device.uid).map(str::to_owned);
     }
     available_devices
 }
 
-pub fn available_devices() -> Result<Vec<Device>, Box<dyn Error>> {
+/// Returns the connected eReaders, recognized either as one of the built-in models or by a
+/// profile in the configuration.
+pub fn available_devices(config: &Config) -> Result<Vec<Device>, Error> {
     let devices = mounted_devices()?;
-    Ok(filter(devices))
+    Ok(filter(devices, config))
+}
+
+/// Returns the available device with the given UID or friendly name, as shown by
+/// `libri device list`.
+pub fn find_device(config: &Config, id: &str) -> Result<Device, Error> {
+    available_devices(config)?
+        .into_iter()
+        .find(|device| device.is_identified_by(id))
+        .ok_or_else(|| {
+            Error::Device(format!(
+                "no device with UID or name '{}'. See 'libri device list'",
+                id
+            ))
+        })
+}
+
+/// Returns a device for `target` without going through USB detection. The target is either the
+/// name of a profile, which must set `mount_path`, or the path of any directory, which is treated
+/// as a device accepting EPUBs in its root.
+pub fn manual_target(config: &Config, target: &str) -> Result<Device, Error> {
+    let profile = config.devices.get(target);
+    let usb_info = match profile {
+        Some(profile) => {
+            let mount_path = profile
+                .mount_path
+                .clone()
+                .ok_or_else(|| Error::Device(format!("profile '{}' has no mount_path", target)))?;
+            MassStorage::new(
+                mount_path,
+                profile.vendor_id.unwrap_or_default(),
+                profile.product_id.unwrap_or_default(),
+                &profile.book_dir,
+                &profile.formats,
+            )
+        }
+        None => MassStorage::new(PathBuf::from(target), 0, 0, "", &["epub"]),
+    };
+    if !usb_info.mount_dir().is_dir() {
+        return Err(Error::Device(format!(
+            "'{}' is not a directory",
+            usb_info.mount_dir().display()
+        )));
+    }
+
+    let mut device = Device::new(target.to_owned(), String::new(), Box::new(usb_info), None);
+    // Manual targets have no serial number, so tell them apart by their mount directory, however
+    // it was spelled.
+    let mount_dir = device.mount_dir().canonicalize()?;
+    device.uid = format!("{:08x}", fnv1a(mount_dir.as_os_str().as_encoded_bytes()));
+    device.profile = profile.map(|_| target.to_owned());
+    Ok(device)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use registry::{KINDLE_VENDOR_ID, KOBO_VENDOR_ID};
 
     #[test]
-    #[cfg(target_os = "macos")] // TODO: Can run against all platforms once support is added
     fn filter_devices() {
         let devices = vec![
             MountedDevice {
@@ -114,7 +379,16 @@ mod tests {
                 manufacturer: "Kobo".to_string(),
                 name: "Libra 2".to_string(),
                 vendor_id: KOBO_VENDOR_ID,
-                product_id: LIBRA_2_PRODUCT_ID,
+                product_id: 0x4234,
+                serial: Some("N418123456789".to_string()),
+            },
+            MountedDevice {
+                mount_point: PathBuf::from("/path/to/kindle"),
+                manufacturer: "Amazon".to_string(),
+                name: String::new(),
+                vendor_id: KINDLE_VENDOR_ID,
+                product_id: 0x0004,
+                serial: None,
             },
             MountedDevice {
                 mount_point: PathBuf::from("/path/to/other"),
@@ -122,13 +396,138 @@ mod tests {
                 name: "Name".to_string(),
                 vendor_id: 1,
                 product_id: 2,
+                serial: None,
             },
         ];
-        let available_devices = filter(devices);
-        assert_eq!(available_devices.len(), 1);
+        let available_devices = filter(devices, &Config::default());
+        assert_eq!(available_devices.len(), 2);
 
         let device = &available_devices[0];
+        assert_eq!(device.name(), "Libra 2");
         assert_eq!(device.usb_info.vendor_id(), KOBO_VENDOR_ID);
-        assert_eq!(device.usb_info.product_id(), LIBRA_2_PRODUCT_ID);
+        assert_eq!(device.usb_info.product_id(), 0x4234);
+
+        let device = &available_devices[1];
+        assert_eq!(device.name(), "Kindle");
+        assert_eq!(device.mount_dir(), Path::new("/path/to/kindle"));
+    }
+
+    #[test]
+    fn filter_devices_by_profile() {
+        let mut config = Config::default();
+        config.devices.insert(
+            "my-reader".to_owned(),
+            DeviceConfig {
+                volume_label: Some("READER".to_owned()),
+                book_dir: "books".to_owned(),
+                formats: vec!["epub".to_owned()],
+                ..DeviceConfig::default()
+            },
+        );
+        let devices = vec![
+            MountedDevice {
+                mount_point: PathBuf::from("/media/user/READER"),
+                vendor_id: 1,
+                product_id: 2,
+                ..MountedDevice::default()
+            },
+            MountedDevice {
+                mount_point: PathBuf::from("/media/user/OTHER"),
+                vendor_id: 1,
+                product_id: 2,
+                ..MountedDevice::default()
+            },
+        ];
+        let available_devices = filter(devices, &config);
+        assert_eq!(available_devices.len(), 1);
+
+        let device = &available_devices[0];
+        assert_eq!(device.name(), "my-reader");
+        assert_eq!(device.mount_dir(), Path::new("/media/user/READER"));
+        assert_eq!(device.profile.as_deref(), Some("my-reader"));
+    }
+
+    #[test]
+    fn manual_targets() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.devices.insert(
+            "my-reader".to_owned(),
+            DeviceConfig {
+                mount_path: Some(dir.path().to_path_buf()),
+                ..DeviceConfig::default()
+            },
+        );
+        config
+            .devices
+            .insert("office-kobo".to_owned(), DeviceConfig::default());
+
+        let profile = manual_target(&config, "my-reader").unwrap();
+        assert_eq!(profile.profile.as_deref(), Some("my-reader"));
+        let path = dir.path().join(".").to_str().unwrap().to_owned();
+        let directory = manual_target(&config, &path).unwrap();
+        assert_eq!(directory.profile, None);
+        assert_eq!(profile.uid, directory.uid);
+
+        assert!(matches!(
+            manual_target(&config, "office-kobo"),
+            Err(Error::Device(message)) if message == "profile 'office-kobo' has no mount_path"
+        ));
+    }
+
+    #[test]
+    fn uids_differ_by_serial() {
+        let kobo = |mount_point: &str, serial: &str| MountedDevice {
+            mount_point: PathBuf::from(mount_point),
+            vendor_id: KOBO_VENDOR_ID,
+            product_id: 0x4234,
+            serial: Some(serial.to_owned()),
+            ..MountedDevice::default()
+        };
+        let mut config = Config::default();
+        config.device_names.insert(
+            uid(KOBO_VENDOR_ID, 0x4234, Some("N418A")),
+            "office".to_owned(),
+        );
+        let devices = vec![kobo("/media/a", "N418A"), kobo("/media/b", "N418B")];
+        let available_devices = filter(devices, &config);
+        assert_eq!(available_devices.len(), 2);
+
+        let (first, second) = (&available_devices[0], &available_devices[1]);
+        assert_ne!(first.uid(), second.uid());
+        assert_eq!(first.uid().len(), 8);
+        assert_eq!(first.friendly_name(), Some("office"));
+        assert_eq!(second.friendly_name(), None);
+        assert!(first.is_identified_by("office"));
+        assert!(second.is_identified_by(second.uid()));
+
+        // The UID only depends on the device, not on where it is mounted.
+        let again = filter(vec![kobo("/media/c", "N418B")], &config);
+        assert_eq!(again[0].uid(), second.uid());
+    }
+
+    #[test]
+    fn settings_by_friendly_name() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("config.ini"),
+            format!(
+                "[device-names]\n{} = Office Kobo\n\n[device \"Office Kobo\"]\nkepub = true\n",
+                uid(KOBO_VENDOR_ID, 0x4234, Some("N418A"))
+            ),
+        )
+        .unwrap();
+        let config = crate::config::read(Some(dir.path())).unwrap();
+        let devices = vec![MountedDevice {
+            mount_point: PathBuf::from("/media/KOBO"),
+            vendor_id: KOBO_VENDOR_ID,
+            product_id: 0x4234,
+            serial: Some("N418A".to_owned()),
+            ..MountedDevice::default()
+        }];
+
+        let device = &filter(devices, &config)[0];
+        assert_eq!(device.friendly_name(), Some("Office Kobo"));
+        assert!(device.settings(&config).kepub);
     }
 }
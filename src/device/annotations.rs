@@ -0,0 +1,230 @@
+//! Exports the reading progress, highlights and notes stored on a device.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::super::format::epub;
+use super::super::{config, list, Ebook};
+
+/// How far along a book the reader is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReadStatus {
+    Unread,
+    Reading,
+    Finished,
+}
+
+impl ReadStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            ReadStatus::Unread => "unread",
+            ReadStatus::Reading => "reading",
+            ReadStatus::Finished => "finished",
+        }
+    }
+}
+
+/// A highlight or note made while reading a book.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Annotation {
+    /// The highlighted passage.
+    pub highlight: Option<String>,
+    /// The note attached to the passage.
+    pub note: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// The reading state of a book on a device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadingState {
+    /// The path of the book on the device, if it was sideloaded rather than bought on the device.
+    pub path: Option<PathBuf>,
+    pub title: String,
+    pub author: Option<String>,
+    pub status: ReadStatus,
+    /// How much of the book has been read, from 0 to 100.
+    pub percent_read: u32,
+    pub last_read: Option<DateTime<Utc>>,
+    pub annotations: Vec<Annotation>,
+}
+
+/// The format annotations are exported in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Markdown,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "markdown" => Ok(Format::Markdown),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format '{}' (expected one of markdown, json)",
+                s
+            )),
+        }
+    }
+}
+
+/// The reading state of a book, mapped back to the library. The field names are relied upon by
+/// scripts, so existing fields must not be renamed.
+#[derive(Serialize)]
+struct Record<'a> {
+    identifier: Option<&'a str>,
+    title: &'a str,
+    author: Option<&'a str>,
+    in_library: bool,
+    status: &'static str,
+    percent_read: u32,
+    last_read: Option<DateTime<Utc>>,
+    annotations: &'a [Annotation],
+}
+
+impl<'a> Record<'a> {
+    /// Creates the record of `state`, using the metadata of the matching library book if there
+    /// is one.
+    fn new(
+        state: &'a ReadingState,
+        identifier: Option<&'a str>,
+        library: &HashMap<&str, &'a Ebook>,
+    ) -> Record<'a> {
+        let ebook = identifier.and_then(|identifier| library.get(identifier).copied());
+        Record {
+            identifier,
+            title: ebook.map_or(&state.title, |ebook| ebook.title()),
+            author: ebook
+                .map(|ebook| ebook.author())
+                .or(state.author.as_deref()),
+            in_library: ebook.is_some(),
+            status: state.status.name(),
+            percent_read: state.percent_read,
+            last_read: state.last_read,
+            annotations: &state.annotations,
+        }
+    }
+}
+
+/// Writes the records as a Markdown document with a section per book.
+fn write_markdown<W: Write>(writer: &mut W, records: &[Record]) -> io::Result<()> {
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "## {}", record.title)?;
+        writeln!(writer)?;
+        if let Some(author) = record.author {
+            writeln!(writer, "- Author: {}", author)?;
+        }
+        writeln!(
+            writer,
+            "- Status: {} ({}%)",
+            record.status, record.percent_read
+        )?;
+        if let Some(last_read) = record.last_read {
+            writeln!(writer, "- Last read: {}", last_read.format("%Y-%m-%d"))?;
+        }
+        if !record.in_library {
+            writeln!(writer, "- Not in the library")?;
+        }
+        for annotation in record.annotations {
+            writeln!(writer)?;
+            if let Some(highlight) = &annotation.highlight {
+                for line in highlight.trim().lines() {
+                    writeln!(writer, "> {}", line)?;
+                }
+            }
+            if let Some(note) = &annotation.note {
+                if annotation.highlight.is_some() {
+                    writeln!(writer)?;
+                }
+                writeln!(writer, "{}", note.trim())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(config: &config::Config, uid: &str, format: Format) -> Result<(), Box<dyn Error>> {
+    let device = super::find_device(config, uid)?;
+    let states: Vec<ReadingState> = device
+        .reading_states()?
+        .into_iter()
+        .filter(|state| state.status != ReadStatus::Unread || !state.annotations.is_empty())
+        .collect();
+    let ebooks = list::get_ebooks(&config.library)?;
+    let library: HashMap<&str, &Ebook> = ebooks
+        .iter()
+        .map(|ebook| (ebook.identifier.as_str(), ebook))
+        .collect();
+
+    // Books on the device are matched to the library by the identifier in their metadata.
+    let identifiers: Vec<Option<String>> = states
+        .iter()
+        .map(|state| {
+            let path = state.path.as_ref()?;
+            epub::parse(path).ok().map(|ebook| ebook.identifier)
+        })
+        .collect();
+    let records: Vec<Record> = states
+        .iter()
+        .zip(&identifiers)
+        .map(|(state, identifier)| Record::new(state, identifier.as_deref(), &library))
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        Format::Markdown => write_markdown(&mut stdout, &records)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &records)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_export() {
+        let state = ReadingState {
+            path: None,
+            title: "Emma".to_owned(),
+            author: Some("Jane Austen".to_owned()),
+            status: ReadStatus::Reading,
+            percent_read: 42,
+            last_read: None,
+            annotations: vec![
+                Annotation {
+                    highlight: Some("Emma Woodhouse, handsome, clever, and rich".to_owned()),
+                    note: Some("Opening line".to_owned()),
+                    created: None,
+                },
+                Annotation {
+                    highlight: None,
+                    note: Some("Check the date".to_owned()),
+                    created: None,
+                },
+            ],
+        };
+        let records = [Record::new(&state, None, &HashMap::new())];
+        let mut output = Vec::new();
+        write_markdown(&mut output, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "## Emma\n\n- Author: Jane Austen\n- Status: reading (42%)\n- Not in the library\n\n\
+             > Emma Woodhouse, handsome, clever, and rich\n\nOpening line\n\nCheck the date\n"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Lists the books stored on a device and how they relate to the library.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tabwriter::TabWriter;
+
+use super::super::format::epub;
+use super::super::output::{self, Format};
+use super::super::{common, config, list, Ebook};
+
+/// How a book on the device relates to the library.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    /// The book is in the library and the copy on the device is up to date.
+    Library,
+    /// The book is not in the library.
+    DeviceOnly,
+    /// The book is in the library, but the library copy has changed since it was put on the
+    /// device.
+    Outdated,
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Library => "library",
+            Status::DeviceOnly => "device only",
+            Status::Outdated => "outdated",
+        }
+    }
+}
+
+/// A book found on the device.
+struct DeviceBook {
+    ebook: Ebook,
+    status: Status,
+}
+
+/// Returns the status of a book on the device, compared to the books in the library.
+fn status(ebook: &Ebook, library: &HashMap<&str, &Ebook>) -> Status {
+    match library.get(ebook.identifier.as_str()) {
+        None => Status::DeviceOnly,
+        // Copies to the device are newer than the library file they were made from, so a library
+        // file modified after the copy was made has been updated since. KEPUB copies are converted
+        // on upload, so their size never matches the original.
+        Some(original)
+            if (!is_kepub(&ebook.path) && original.size != ebook.size)
+                || original.modified > ebook.modified =>
+        {
+            Status::Outdated
+        }
+        Some(_) => Status::Library,
+    }
+}
+
+/// Returns true if `path` is a book converted to KEPUB on upload.
+pub fn is_kepub(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        name.to_string_lossy()
+            .to_lowercase()
+            .ends_with(".kepub.epub")
+    })
+}
+
+/// Parses every ebook stored below `mount_dir`, comparing them against the books in the library.
+/// Books which fail to parse are reported and left out.
+fn scan(mount_dir: &Path, library: &[Ebook]) -> Result<Vec<DeviceBook>, Box<dyn Error>> {
+    let library: HashMap<&str, &Ebook> = library
+        .iter()
+        .map(|ebook| (ebook.identifier.as_str(), ebook))
+        .collect();
+    let mut paths = common::find_ebooks(mount_dir)?;
+    paths.sort();
+
+    let mut books = Vec::new();
+    for path in paths {
+        match epub::parse(&path) {
+            Ok(ebook) => books.push(DeviceBook {
+                status: status(&ebook, &library),
+                ebook,
+            }),
+            Err(e) => eprintln!("skipping '{}' -- {}", path.display(), e),
+        }
+    }
+    Ok(books)
+}
+
+/// The representation of a book on a device in machine-readable output. The field names are
+/// relied upon by scripts, so existing fields must not be renamed.
+#[derive(Serialize)]
+struct Record<'a> {
+    status: &'static str,
+    identifier: &'a str,
+    title: &'a str,
+    author: &'a str,
+    path: PathBuf,
+}
+
+/// The names of the fields of a record, in the order they are printed as delimited values.
+const RECORD_FIELDS: &[&str] = &["status", "identifier", "title", "author", "path"];
+
+impl<'a> Record<'a> {
+    fn new(book: &'a DeviceBook, mount_dir: &Path) -> Record<'a> {
+        let path = &book.ebook.path;
+        Record {
+            status: book.status.name(),
+            identifier: &book.ebook.identifier,
+            title: &book.ebook.title,
+            author: book.ebook.author(),
+            path: path.strip_prefix(mount_dir).unwrap_or(path).to_path_buf(),
+        }
+    }
+
+    /// Returns the values of the record in the order of `RECORD_FIELDS`.
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.status.to_owned(),
+            self.identifier.to_owned(),
+            self.title.to_owned(),
+            self.author.to_owned(),
+            self.path.display().to_string(),
+        ]
+    }
+}
+
+pub fn run(config: &config::Config, uid: &str, format: Format) -> Result<(), Box<dyn Error>> {
+    let device = super::find_device(config, uid)?;
+    let books = scan(device.mount_dir(), &list::get_ebooks(&config.library)?)?;
+    let records: Vec<Record> = books
+        .iter()
+        .map(|book| Record::new(book, device.mount_dir()))
+        .collect();
+    match format {
+        Format::Table => {
+            let mut tw = TabWriter::new(io::stdout());
+            writeln!(&mut tw, "\x1b[1mStatus\tTitle\tAuthor\tPath\x1b[0m")?;
+            for record in &records {
+                writeln!(
+                    &mut tw,
+                    "{}\t{}\t{}\t{}",
+                    record.status,
+                    record.title,
+                    record.author,
+                    record.path.display()
+                )?;
+            }
+            tw.flush()?;
+            Ok(())
+        }
+        Format::Json => output::write_json(&records),
+        Format::Csv | Format::Tsv => {
+            let rows: Vec<Vec<String>> = records.iter().map(Record::values).collect();
+            output::write_delimited(format, RECORD_FIELDS, &rows)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::fixtures::write_epub;
+
+    #[test]
+    fn scan_classifies_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let library_dir = dir.path().join("library");
+        let mount = dir.path().join("device");
+        fs::create_dir_all(&library_dir).unwrap();
+        fs::create_dir_all(mount.join("Jane Austen")).unwrap();
+
+        write_epub(
+            &library_dir.join("emma.epub"),
+            "id-emma",
+            "Emma",
+            "Jane Austen",
+        );
+        write_epub(
+            &library_dir.join("persuasion.epub"),
+            "id-persuasion",
+            "Persuasion, Revised Edition",
+            "Jane Austen",
+        );
+        write_epub(
+            &mount.join("Jane Austen/emma.epub"),
+            "id-emma",
+            "Emma",
+            "Jane Austen",
+        );
+        write_epub(
+            &mount.join("Jane Austen/persuasion.epub"),
+            "id-persuasion",
+            "Persuasion",
+            "Jane Austen",
+        );
+        write_epub(
+            &library_dir.join("sense.epub"),
+            "id-sense",
+            "Sense and Sensibility",
+            "Jane Austen",
+        );
+        // Converted copies differ in size from the original.
+        write_epub(
+            &mount.join("Jane Austen/sense.kepub.epub"),
+            "id-sense",
+            "Sense and Sensibility (KEPUB)",
+            "Jane Austen",
+        );
+        write_epub(
+            &mount.join("dracula.epub"),
+            "id-dracula",
+            "Dracula",
+            "Bram Stoker",
+        );
+        fs::write(mount.join("broken.epub"), "not an epub").unwrap();
+
+        let library = list::get_ebooks(&library_dir).unwrap();
+        let books = scan(&mount, &library).unwrap();
+        let statuses: Vec<(&str, Status)> = books
+            .iter()
+            .map(|book| (book.ebook.title(), book.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("Emma", Status::Library),
+                ("Persuasion", Status::Outdated),
+                ("Sense and Sensibility (KEPUB)", Status::Library),
+                ("Dracula", Status::DeviceOnly),
+            ]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! The size of and space left on the file system a device is mounted at.
+
+use std::io;
+use std::path::Path;
+
+/// The total and free space of a file system, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capacity {
+    pub total: u64,
+    /// The space available to unprivileged users, which excludes blocks reserved for root.
+    pub free: u64,
+}
+
+/// Returns the capacity of the file system `path` is on.
+#[cfg(unix)]
+pub fn capacity(path: &Path) -> io::Result<Capacity> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read after statvfs succeeds.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    let block_size = stat.f_frsize as u64;
+    Ok(Capacity {
+        total: stat.f_blocks as u64 * block_size,
+        free: stat.f_bavail as u64 * block_size,
+    })
+}
+
+/// Returns the capacity of the file system `path` is on.
+#[cfg(not(unix))]
+pub fn capacity(_path: &Path) -> io::Result<Capacity> {
+    // TODO: Implement with GetDiskFreeSpaceExW
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "capacity not yet implemented for this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_of_directory() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let Capacity { total, free } = capacity(dir.path()).unwrap();
+        assert!(total > 0);
+        assert!(free <= total);
+        assert!(capacity(&dir.path().join("missing")).is_err());
+    }
+}
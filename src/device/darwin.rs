@@ -14,7 +14,6 @@
 #![allow(non_camel_case_types, non_upper_case_globals)]
 
 use std::collections::HashMap;
-use std::error::Error;
 use std::ffi::CStr;
 use std::mem::{self, MaybeUninit};
 use std::path::PathBuf;
@@ -28,6 +27,8 @@ use core_foundation::string::{CFString, CFStringRef};
 use libc::{c_char, c_int, getfsstat, statfs, MNT_NOWAIT};
 use mach::{kern_return, port};
 
+use crate::Error;
+
 pub type IOOptionBits = u32;
 
 pub const kIOMainPortDefault: mach_port_t = port::MACH_PORT_NULL;
@@ -37,6 +38,7 @@ pub const kUSBVendorID: *const c_char = "idVendor\0".as_ptr() as *const c_char;
 pub const kUSBVendorString: *const c_char = "USB Vendor Name\0".as_ptr() as *const c_char;
 pub const kUSBProductID: *const c_char = "idProduct\0".as_ptr() as *const c_char;
 pub const kUSBProductString: *const c_char = "USB Product Name\0".as_ptr() as *const c_char;
+pub const kUSBSerialNumberString: *const c_char = "USB Serial Number\0".as_ptr() as *const c_char;
 
 pub const kIORegistryIterateRecursively: IOOptionBits = 0x00000001;
 // pub const kIORegistryIterateParents: IOOptionBits = 0x00000002;
@@ -116,16 +118,16 @@ where
     parsed
 }
 
-fn mounted_file_systems() -> Result<HashMap<String, String>, Box<dyn Error>> {
+fn mounted_file_systems() -> Result<HashMap<String, String>, Error> {
     let mut results: HashMap<String, String> = HashMap::new();
 
     unsafe {
         let mut num = getfsstat(std::ptr::null_mut(), 0, MNT_NOWAIT);
         if num == -1 {
-            panic!(
+            return Err(Error::Device(format!(
                 "failed to read the number of mounted file systems: {}",
                 errno
-            );
+            )));
         }
 
         // MNT_NOWAIT causes getfsstat to immediately return instead of blocking on slow file
@@ -139,7 +141,10 @@ fn mounted_file_systems() -> Result<HashMap<String, String>, Box<dyn Error>> {
             MNT_NOWAIT,
         );
         if num == -1 {
-            panic!("failed to retrieve info on mounted file systems: {}", errno);
+            return Err(Error::Device(format!(
+                "failed to retrieve info on mounted file systems: {}",
+                errno
+            )));
         }
         mounted.set_len(num as usize);
 
@@ -156,20 +161,22 @@ fn mounted_file_systems() -> Result<HashMap<String, String>, Box<dyn Error>> {
     Ok(results)
 }
 
-pub fn usb_devices() -> Result<Vec<super::MountedDevice>, Box<dyn Error>> {
-    let mounted = mounted_file_systems().unwrap();
+pub fn usb_devices() -> Result<Vec<super::MountedDevice>, Error> {
+    let mounted = mounted_file_systems()?;
     let mut devices: Vec<super::MountedDevice> = Vec::new();
 
     unsafe {
         let matching_dict = IOServiceMatching(b"IOUSBHostDevice\0".as_ptr() as *const c_char);
         if matching_dict.as_ref().is_none() {
-            panic!("failed to create matching dictionary for USB devices");
+            return Err(Error::Device(
+                "failed to create matching dictionary for USB devices".to_owned(),
+            ));
         }
 
         let mut iterator: io_iterator_t = MaybeUninit::uninit().assume_init();
         let ret = IOServiceGetMatchingServices(kIOMainPortDefault, matching_dict, &mut iterator);
         if ret != kern_return::KERN_SUCCESS {
-            panic!("failed to retrieve USB devices");
+            return Err(Error::Device("failed to retrieve USB devices".to_owned()));
         }
 
         let mut service = IOIteratorNext(iterator);
@@ -179,6 +186,7 @@ pub fn usb_devices() -> Result<Vec<super::MountedDevice>, Box<dyn Error>> {
             let vendor_name = find_property::<String>(service, CFSTR(kUSBVendorString));
             let product_id = find_property::<i32>(service, CFSTR(kUSBProductID));
             let product_name = find_property::<String>(service, CFSTR(kUSBProductString));
+            let serial = find_property::<String>(service, CFSTR(kUSBSerialNumberString));
 
             if let (
                 Some(mut bsd_name),
@@ -197,6 +205,7 @@ pub fn usb_devices() -> Result<Vec<super::MountedDevice>, Box<dyn Error>> {
                         name: product_name,
                         vendor_id: vendor_id.try_into().unwrap(),
                         product_id: product_id.try_into().unwrap(),
+                        serial,
                     });
                 }
             }
@@ -0,0 +1,222 @@
+//! Safely ejects devices, so they can be unplugged without corrupting the books on them.
+
+use std::error::Error as StdError;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use super::super::config;
+use super::Device;
+use crate::Error;
+
+/// Runs external programs. Ejecting is done by the tools of the operating system, which are run
+/// through this trait so the commands can be checked without a device attached.
+pub trait CommandRunner {
+    /// Runs `program` with `args`, returning an error if it can't be started or doesn't exit
+    /// successfully.
+    fn run(&mut self, program: &str, args: &[&OsStr]) -> io::Result<()>;
+}
+
+/// Runs programs as child processes.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&mut self, program: &str, args: &[&OsStr]) -> io::Result<()> {
+        let output = Command::new(program).args(args).output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::other(format!(
+            "{} failed: {}",
+            program,
+            match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_owned(),
+            }
+        )))
+    }
+}
+
+/// Unmounts the file system at `mount_dir` (Linux specific). udisks is preferred, since it lets
+/// users unmount devices they mounted without root, with umount as the fallback.
+#[cfg(target_os = "linux")]
+fn unmount(mount_dir: &Path, runner: &mut dyn CommandRunner) -> io::Result<()> {
+    unmount_linux(
+        mount_dir,
+        super::linux::mount_source(mount_dir).as_deref(),
+        runner,
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn unmount_linux(
+    mount_dir: &Path,
+    source: Option<&Path>,
+    runner: &mut dyn CommandRunner,
+) -> io::Result<()> {
+    if let Some(source) = source {
+        let args = [
+            "unmount".as_ref(),
+            "--block-device".as_ref(),
+            source.as_os_str(),
+        ];
+        if runner.run("udisksctl", &args).is_ok() {
+            return Ok(());
+        }
+    }
+    runner.run("umount", &[mount_dir.as_os_str()])
+}
+
+/// Unmounts the file system at `mount_dir` (macOS specific).
+#[cfg(target_os = "macos")]
+fn unmount(mount_dir: &Path, runner: &mut dyn CommandRunner) -> io::Result<()> {
+    runner.run("diskutil", &["eject".as_ref(), mount_dir.as_os_str()])
+}
+
+/// Unmounts the file system at `mount_dir` (Windows specific).
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn unmount(_mount_dir: &Path, _runner: &mut dyn CommandRunner) -> io::Result<()> {
+    // TODO: Implement
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "ejecting not yet implemented for this platform",
+    ))
+}
+
+/// Returns true if a file system is mounted at `path`, which is the case when it is on a different
+/// device than its parent directory.
+#[cfg(unix)]
+pub fn is_mount_point(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        // The root directory is always a mount point.
+        None => return true,
+    };
+    match (path.metadata(), parent.metadata()) {
+        (Ok(metadata), Ok(parent)) => metadata.dev() != parent.dev(),
+        _ => false,
+    }
+}
+
+/// Returns true if a file system is mounted at `path` (Windows specific).
+#[cfg(not(unix))]
+pub fn is_mount_point(_path: &Path) -> bool {
+    // TODO: Implement
+    true
+}
+
+/// Flushes the books written to the device and unmounts it.
+pub fn eject(device: &Device, runner: &mut dyn CommandRunner) -> Result<(), Error> {
+    let mount_dir = device.mount_dir();
+    let failed =
+        |e: io::Error| Error::Device(format!("failed to eject '{}': {}", mount_dir.display(), e));
+    runner.run("sync", &[]).map_err(failed)?;
+    unmount(mount_dir, runner).map_err(failed)?;
+    println!("ejected {}; it is safe to unplug", device.name());
+    Ok(())
+}
+
+pub fn run(config: &config::Config, uid: &str) -> Result<(), Box<dyn StdError>> {
+    let device = super::find_device(config, uid)?;
+    eject(&device, &mut SystemRunner)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::device::mass_storage::MassStorage;
+
+    /// Records the commands it is asked to run instead of running them. Programs listed in
+    /// `failing` return an error.
+    #[derive(Default)]
+    struct FakeRunner {
+        commands: Vec<String>,
+        failing: Vec<&'static str>,
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(&mut self, program: &str, args: &[&OsStr]) -> io::Result<()> {
+            let mut command = vec![program.to_owned()];
+            command.extend(args.iter().map(|arg| arg.to_string_lossy().into_owned()));
+            self.commands.push(command.join(" "));
+            if self.failing.contains(&program) {
+                return Err(io::Error::other(format!("{} failed", program)));
+            }
+            Ok(())
+        }
+    }
+
+    fn device(mount_dir: &str) -> Device {
+        let usb_info = MassStorage::new(PathBuf::from(mount_dir), 1, 2, "", &["epub"]);
+        Device::new("Reader".to_owned(), String::new(), Box::new(usb_info), None)
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn eject_with_udisks() {
+        let mut runner = FakeRunner::default();
+        let source = Path::new("/dev/sdb1");
+        unmount_linux(Path::new("/media/KOBO"), Some(source), &mut runner).unwrap();
+        assert_eq!(
+            runner.commands,
+            ["udisksctl unmount --block-device /dev/sdb1"]
+        );
+
+        let mut runner = FakeRunner {
+            failing: vec!["udisksctl"],
+            ..FakeRunner::default()
+        };
+        unmount_linux(Path::new("/media/KOBO"), Some(source), &mut runner).unwrap();
+        assert_eq!(
+            runner.commands,
+            [
+                "udisksctl unmount --block-device /dev/sdb1",
+                "umount /media/KOBO"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn eject_syncs_before_unmounting() {
+        let mut runner = FakeRunner::default();
+        eject(&device("/media/KOBO"), &mut runner).unwrap();
+        assert_eq!(runner.commands, ["sync", "umount /media/KOBO"]);
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn eject_syncs_before_unmounting() {
+        let mut runner = FakeRunner::default();
+        eject(&device("/Volumes/KOBO"), &mut runner).unwrap();
+        assert_eq!(runner.commands, ["sync", "diskutil eject /Volumes/KOBO"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn mount_points() {
+        assert!(is_mount_point(Path::new("/")));
+        let dir = assert_fs::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("reader")).unwrap();
+        assert!(!is_mount_point(&dir.path().join("reader")));
+        assert!(!is_mount_point(&dir.path().join("missing")));
+    }
+
+    #[test]
+    fn eject_failure() {
+        let mut runner = FakeRunner {
+            failing: vec!["sync"],
+            ..FakeRunner::default()
+        };
+        let result = eject(&device("/media/KOBO"), &mut runner);
+        assert!(matches!(result, Err(Error::Device(_))));
+        assert_eq!(runner.commands, ["sync"]);
+    }
+}
@@ -1,49 +1,103 @@
+mod database;
+
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
-use super::super::{common, Ebook};
+use super::super::config::DeviceConfig;
+use super::super::format::kepub;
+use super::super::template::Template;
+use super::super::{Ebook, Error};
+use super::annotations::ReadingState;
+use super::mass_storage::MassStorage;
+use super::registry::Model;
 use super::UsbDevice;
 
-pub const KOBO_VENDOR_ID: u16 = 0x2237;
-pub const LIBRA_2_PRODUCT_ID: u16 = 0x4234;
-
-pub struct Libra2 {
-    mount_dir: PathBuf,
+/// A Kobo eReader. Kobos store books like any other mass storage device, but can also read
+/// KEPUBs and keep a database of reading progress.
+pub struct Kobo {
+    storage: MassStorage,
 }
 
-impl Libra2 {
-    pub fn new(mount_dir: PathBuf) -> Libra2 {
-        Libra2 { mount_dir }
+impl Kobo {
+    pub fn new(mount_dir: PathBuf, model: &Model) -> Kobo {
+        Kobo {
+            storage: MassStorage::from_model(mount_dir, model),
+        }
     }
 }
 
-impl UsbDevice for Libra2 {
+impl UsbDevice for Kobo {
     fn mount_dir(&self) -> &Path {
-        self.mount_dir.as_path()
+        self.storage.mount_dir()
     }
 
     fn vendor_id(&self) -> u16 {
-        KOBO_VENDOR_ID
+        self.storage.vendor_id()
     }
 
     fn product_id(&self) -> u16 {
-        LIBRA_2_PRODUCT_ID
+        self.storage.product_id()
     }
 
-    // TODO: Add option to auto-convert epubs to kepubs!
-    fn upload_ebook(&self, ebook: &Ebook, library: &Path, dry_run: bool) -> Result<(), io::Error> {
-        // TODO: Factor out any common logic that can be reused across devices
-        let mut destination = self.mount_dir().to_path_buf();
-        destination.push(ebook.path.strip_prefix(library).unwrap());
-        if destination.exists() {
-            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "already on device").into());
+    fn destination(
+        &self,
+        ebook: &Ebook,
+        template: &Template,
+        settings: &DeviceConfig,
+    ) -> Result<PathBuf, io::Error> {
+        let mut destination = self.storage.destination(ebook, template, settings)?;
+        if is_kepub(ebook, settings) {
+            // Kobo only treats books as KEPUBs when their name ends in `.kepub.epub`.
+            destination.set_extension("kepub.epub");
+        }
+        Ok(destination)
+    }
+
+    fn upload_ebook(
+        &self,
+        ebook: &Ebook,
+        template: &Template,
+        settings: &DeviceConfig,
+        dry_run: bool,
+    ) -> Result<PathBuf, io::Error> {
+        if !is_kepub(ebook, settings) {
+            return self
+                .storage
+                .upload_ebook(ebook, template, settings, dry_run);
         }
 
+        let destination = self.destination(ebook, template, settings)?;
+        if destination.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "already on device",
+            ));
+        }
         if !dry_run {
-            fs::create_dir_all(&destination.parent().unwrap())?;
-            common::copy(&ebook.path, &destination)?;
+            fs::create_dir_all(destination.parent().unwrap())?;
+            if let Err(e) = kepub::convert(&ebook.path, &destination) {
+                // Don't leave a partially written book behind on the device.
+                let _ = fs::remove_file(&destination);
+                return Err(match e {
+                    Error::Io(e) => e,
+                    e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+                });
+            }
         }
-        Ok(())
+        Ok(destination)
+    }
+
+    fn reading_states(&self) -> Result<Vec<ReadingState>, Error> {
+        database::reading_states(self.mount_dir())
     }
 }
+
+/// Returns true if the ebook is converted to a KEPUB when uploading it.
+fn is_kepub(ebook: &Ebook, settings: &DeviceConfig) -> bool {
+    settings.kepub
+        && ebook
+            .path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+}
@@ -0,0 +1,177 @@
+//! Reads the reading state Kobo eReaders keep in `.kobo/KoboReader.sqlite`.
+
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::{Connection, OpenFlags};
+
+use super::super::super::Error;
+use super::super::annotations::{Annotation, ReadStatus, ReadingState};
+
+/// Path of the database, relative to the mount directory of the device.
+const DATABASE_PATH: &str = ".kobo/KoboReader.sqlite";
+
+/// Prefix of the content IDs of books sideloaded onto the internal storage of the device. The
+/// rest of the ID is the path of the book relative to the mount directory.
+const ONBOARD_PREFIX: &str = "file:///mnt/onboard/";
+
+/// The content type of entire books, as opposed to their chapters.
+const BOOK_CONTENT_TYPE: i64 = 6;
+
+/// Parses the timestamps stored by the device, which are in UTC but only sometimes say so.
+fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Some(date.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .map(|date| date.and_utc())
+}
+
+fn read_status(value: i64) -> ReadStatus {
+    match value {
+        1 => ReadStatus::Reading,
+        2 => ReadStatus::Finished,
+        _ => ReadStatus::Unread,
+    }
+}
+
+/// Returns the reading state of every book in the database of the Kobo mounted at `mount_dir`.
+pub fn reading_states(mount_dir: &Path) -> Result<Vec<ReadingState>, Error> {
+    let path = mount_dir.join(DATABASE_PATH);
+    if !path.exists() {
+        return Err(Error::Device(format!(
+            "no reading database found at '{}'",
+            path.display()
+        )));
+    }
+    let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let mut annotations = connection.prepare(
+        "SELECT Text, Annotation, DateCreated FROM Bookmark
+         WHERE VolumeID = ?1 AND (Text IS NOT NULL OR Annotation IS NOT NULL)
+         ORDER BY DateCreated",
+    )?;
+    let mut books = connection.prepare(
+        "SELECT ContentID, Title, Attribution, ReadStatus, ___PercentRead, DateLastRead
+         FROM content WHERE ContentType = ?1 ORDER BY Title",
+    )?;
+    let mut rows = books.query([BOOK_CONTENT_TYPE])?;
+
+    let mut states = Vec::new();
+    while let Some(row) = rows.next()? {
+        let content_id: String = row.get(0)?;
+        let annotations = annotations
+            .query_map([&content_id], |row| {
+                Ok(Annotation {
+                    highlight: row.get(0)?,
+                    note: row.get(1)?,
+                    created: row
+                        .get::<_, Option<String>>(2)?
+                        .and_then(|date| parse_date(&date)),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        states.push(ReadingState {
+            path: content_id
+                .strip_prefix(ONBOARD_PREFIX)
+                .map(|path| mount_dir.join(path)),
+            title: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            author: row.get(2)?,
+            status: read_status(row.get::<_, Option<i64>>(3)?.unwrap_or_default()),
+            percent_read: row.get::<_, Option<u32>>(4)?.unwrap_or_default(),
+            last_read: row
+                .get::<_, Option<String>>(5)?
+                .and_then(|date| parse_date(&date)),
+            annotations,
+        });
+    }
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Creates a database with the parts of the schema used by libri, holding a sideloaded book
+    /// with annotations and a book bought on the device.
+    fn write_database(mount_dir: &Path) {
+        fs::create_dir_all(mount_dir.join(".kobo")).unwrap();
+        let connection = Connection::open(mount_dir.join(DATABASE_PATH)).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE content (
+                     ContentID TEXT NOT NULL, ContentType TEXT NOT NULL, Title TEXT,
+                     Attribution TEXT, ReadStatus INT, ___PercentRead INTEGER, DateLastRead TEXT
+                 );
+                 CREATE TABLE Bookmark (
+                     BookmarkID TEXT NOT NULL, VolumeID TEXT NOT NULL, Text TEXT,
+                     Annotation TEXT, DateCreated TEXT
+                 );
+                 INSERT INTO content VALUES
+                     ('file:///mnt/onboard/Jane Austen/Emma/Emma.epub', 6, 'Emma', 'Jane Austen',
+                      1, 42, '2024-03-02T20:15:00Z'),
+                     ('file:///mnt/onboard/Jane Austen/Emma/Emma.epub#(1)OEBPS/ch1.xhtml', 9,
+                      'Chapter 1', NULL, 0, 0, NULL),
+                     ('0b8a9d0c-5d4e-4a49-8f6c-2e1f0f6bd3a1', 6, 'Dracula', 'Bram Stoker',
+                      2, 100, '2024-01-05T08:00:00.000');
+                 INSERT INTO Bookmark VALUES
+                     ('b2', 'file:///mnt/onboard/Jane Austen/Emma/Emma.epub', NULL,
+                      'Check the date', '2024-03-02T20:10:00Z'),
+                     ('b1', 'file:///mnt/onboard/Jane Austen/Emma/Emma.epub',
+                      'Emma Woodhouse, handsome, clever, and rich', NULL, '2024-03-01T19:00:00Z'),
+                     ('b3', 'file:///mnt/onboard/Jane Austen/Emma/Emma.epub', NULL, NULL,
+                      '2024-03-01T19:05:00Z');",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn read_reading_states() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        write_database(dir.path());
+
+        let states = reading_states(dir.path()).unwrap();
+        assert_eq!(states.len(), 2);
+
+        let dracula = &states[0];
+        assert_eq!(dracula.path, None);
+        assert_eq!(dracula.status, ReadStatus::Finished);
+        assert_eq!(
+            dracula.last_read,
+            Some(Utc.with_ymd_and_hms(2024, 1, 5, 8, 0, 0).unwrap())
+        );
+
+        let emma = &states[1];
+        assert_eq!(
+            emma.path,
+            Some(dir.path().join("Jane Austen/Emma/Emma.epub"))
+        );
+        assert_eq!(emma.author.as_deref(), Some("Jane Austen"));
+        assert_eq!(emma.status, ReadStatus::Reading);
+        assert_eq!(emma.percent_read, 42);
+        let annotations: Vec<(Option<&str>, Option<&str>)> = emma
+            .annotations
+            .iter()
+            .map(|a| (a.highlight.as_deref(), a.note.as_deref()))
+            .collect();
+        assert_eq!(
+            annotations,
+            [
+                (Some("Emma Woodhouse, handsome, clever, and rich"), None),
+                (None, Some("Check the date")),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_database() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        assert!(matches!(reading_states(dir.path()), Err(Error::Device(_))));
+    }
+}
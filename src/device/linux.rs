@@ -0,0 +1,278 @@
+//
+// This file contains Linux-specific functionality to recognize connected USB devices. USB devices
+// are discovered by walking sysfs, which exposes the vendor and product information of each
+// device along with the block devices (disks and partitions) the kernel created for it. Block
+// devices are mapped to mount points using the device numbers listed in /proc/self/mountinfo.
+//
+// Both locations can be overridden, which allows the discovery logic to be run against a fake
+// directory tree.
+//
+//   * https://www.kernel.org/doc/html/latest/filesystems/sysfs.html
+//   * https://man7.org/linux/man-pages/man5/proc.5.html (/proc/[pid]/mountinfo)
+//
+
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// Default location where sysfs is mounted.
+pub const SYSFS_ROOT: &str = "/sys";
+/// Default location of the mount table of the current process.
+pub const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+
+/// Returns the mounted USB devices connected to the computer.
+pub fn usb_devices() -> Result<Vec<super::MountedDevice>, Error> {
+    usb_devices_in(Path::new(SYSFS_ROOT), Path::new(MOUNTINFO_PATH))
+}
+
+/// Returns the mounted USB devices described by the sysfs tree at `sysfs_root` and the mount
+/// table at `mountinfo_path`.
+pub fn usb_devices_in(
+    sysfs_root: &Path,
+    mountinfo_path: &Path,
+) -> Result<Vec<super::MountedDevice>, Error> {
+    let mounted = mounted_file_systems(&fs::read_to_string(mountinfo_path)?);
+    let mut devices: Vec<super::MountedDevice> = Vec::new();
+
+    let usb_dir = sysfs_root.join("bus/usb/devices");
+    if !usb_dir.is_dir() {
+        return Ok(devices);
+    }
+
+    for entry in fs::read_dir(usb_dir)? {
+        let path = entry?.path();
+        // Entries without a vendor ID are USB interfaces rather than devices.
+        let (vendor_id, product_id) = match (
+            read_hex_attribute(&path, "idVendor"),
+            read_hex_attribute(&path, "idProduct"),
+        ) {
+            (Some(vendor_id), Some(product_id)) => (vendor_id, product_id),
+            _ => continue,
+        };
+
+        let mut block_devices = Vec::new();
+        find_block_devices(&path, false, &mut block_devices)?;
+        if let Some(mount_point) = block_devices
+            .iter()
+            .find_map(|device_number| mounted.get(device_number))
+        {
+            devices.push(super::MountedDevice {
+                mount_point: mount_point.clone(),
+                manufacturer: read_attribute(&path, "manufacturer").unwrap_or_default(),
+                name: read_attribute(&path, "product").unwrap_or_default(),
+                vendor_id,
+                product_id,
+                serial: read_attribute(&path, "serial").filter(|serial| !serial.is_empty()),
+            });
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Reads a sysfs attribute file, returning its contents with the trailing newline removed.
+fn read_attribute(device: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(device.join(name))
+        .ok()
+        .map(|value| value.trim().to_owned())
+}
+
+/// Reads a sysfs attribute file containing a hexadecimal number, such as a USB vendor ID.
+fn read_hex_attribute(device: &Path, name: &str) -> Option<u16> {
+    read_attribute(device, name).and_then(|value| u16::from_str_radix(&value, 16).ok())
+}
+
+/// Recursively collects the device numbers ("major:minor") of the block devices belonging to the
+/// USB device at `dir`. Symbolic links are not followed, since sysfs uses them to link back to
+/// parent devices and subsystems. Nested USB devices (such as devices connected to a hub) are
+/// skipped, as their block devices belong to them and not to `dir`.
+fn find_block_devices(dir: &Path, in_block: bool, out: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if path.join("idVendor").exists() {
+            continue;
+        }
+
+        let in_block = in_block || entry.file_name() == "block";
+        if in_block {
+            if let Some(device_number) = read_attribute(&path, "dev") {
+                out.push(device_number);
+            }
+        }
+        find_block_devices(&path, in_block, out)?;
+    }
+    Ok(())
+}
+
+/// Parses the contents of a mountinfo file, returning a map of device numbers ("major:minor") to
+/// the path they are mounted at. If a device is mounted multiple times, the first mount point is
+/// used.
+fn mounted_file_systems(mountinfo: &str) -> HashMap<String, PathBuf> {
+    let mut results: HashMap<String, PathBuf> = HashMap::new();
+    for line in mountinfo.lines() {
+        // Format: mount-id parent-id major:minor root mount-point options ...
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() < 5 {
+            continue;
+        }
+        results
+            .entry(fields[2].to_owned())
+            .or_insert_with(|| PathBuf::from(unescape(fields[4])));
+    }
+    results
+}
+
+/// Returns the device node (such as "/dev/sdb1") mounted at `mount_point`.
+pub fn mount_source(mount_point: &Path) -> Option<PathBuf> {
+    find_mount_source(&fs::read_to_string(MOUNTINFO_PATH).ok()?, mount_point)
+}
+
+/// Finds the source of the mount at `mount_point` in the contents of a mountinfo file.
+fn find_mount_source(mountinfo: &str, mount_point: &Path) -> Option<PathBuf> {
+    mountinfo.lines().find_map(|line| {
+        // The optional fields are terminated by a "-", followed by the file system type and the
+        // mount source.
+        let (mount, file_system) = line.split_once(" - ")?;
+        let fields: Vec<&str> = mount.split(' ').collect();
+        if fields.len() < 5 || Path::new(&unescape(fields[4])) != mount_point {
+            return None;
+        }
+        let source = file_system.split(' ').nth(1)?;
+        Some(PathBuf::from(unescape(source)))
+    })
+}
+
+/// Decodes the octal escape sequences (such as "\040" for a space) the kernel uses to encode
+/// whitespace and backslashes in mountinfo paths.
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() {
+            let digits = &bytes[i + 1..i + 4];
+            if digits.iter().all(|d| (b'0'..=b'7').contains(d)) {
+                out.push(digits.iter().fold(0u8, |c, d| (c << 3) | (d - b'0')));
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    fn write_attributes(dir: &Path, attributes: &[(&str, &str)]) {
+        fs::create_dir_all(dir).unwrap();
+        for (name, value) in attributes {
+            fs::write(dir.join(name), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    /// Creates a fake sysfs tree containing a root hub with a Kobo attached to it. The Kobo
+    /// exposes a disk with a single partition.
+    fn fake_sysfs(root: &Path) {
+        let hub = root.join("devices/pci0000:00/usb1");
+        write_attributes(&hub, &[("idVendor", "1d6b"), ("idProduct", "0002")]);
+        let kobo = hub.join("1-1");
+        write_attributes(
+            &kobo,
+            &[
+                ("idVendor", "2237"),
+                ("idProduct", "4234"),
+                ("manufacturer", "Kobo"),
+                ("product", "eReader-4.30.18838"),
+                ("serial", "N418123456789"),
+            ],
+        );
+        let disk = kobo.join("1-1:1.0/host6/target6:0:0/6:0:0:0/block/sdb");
+        write_attributes(&disk, &[("dev", "8:16")]);
+        write_attributes(&disk.join("sdb1"), &[("dev", "8:17"), ("partition", "1")]);
+        // Interface directories don't have a vendor ID and should be ignored.
+        write_attributes(&kobo.join("1-1:1.0"), &[("bInterfaceClass", "08")]);
+
+        let bus = root.join("bus/usb/devices");
+        fs::create_dir_all(&bus).unwrap();
+        symlink(&hub, bus.join("usb1")).unwrap();
+        symlink(&kobo, bus.join("1-1")).unwrap();
+        symlink(kobo.join("1-1:1.0"), bus.join("1-1:1.0")).unwrap();
+    }
+
+    #[test]
+    fn finds_mounted_usb_devices() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let sysfs = dir.path().join("sys");
+        fake_sysfs(&sysfs);
+        let mountinfo = dir.path().join("mountinfo");
+        fs::write(
+            &mountinfo,
+            "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+             98 22 8:17 / /media/user/KOBO\\040eReader rw,nosuid shared:50 - vfat /dev/sdb1 rw\n",
+        )
+        .unwrap();
+
+        let devices = usb_devices_in(&sysfs, &mountinfo).unwrap();
+        assert_eq!(devices.len(), 1);
+        let device = &devices[0];
+        assert_eq!(
+            device.mount_point,
+            PathBuf::from("/media/user/KOBO eReader")
+        );
+        assert_eq!(device.manufacturer, "Kobo");
+        assert_eq!(device.name, "eReader-4.30.18838");
+        assert_eq!(device.vendor_id, 0x2237);
+        assert_eq!(device.product_id, 0x4234);
+        assert_eq!(device.serial.as_deref(), Some("N418123456789"));
+    }
+
+    #[test]
+    fn ignores_unmounted_devices() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let sysfs = dir.path().join("sys");
+        fake_sysfs(&sysfs);
+        let mountinfo = dir.path().join("mountinfo");
+        fs::write(
+            &mountinfo,
+            "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n",
+        )
+        .unwrap();
+
+        assert!(usb_devices_in(&sysfs, &mountinfo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unescape_mount_point() {
+        assert_eq!(unescape("/media/a\\040b"), "/media/a b");
+        assert_eq!(unescape("/media/a\\134b"), "/media/a\\b");
+        assert_eq!(unescape("/media/plain"), "/media/plain");
+        assert_eq!(unescape("/media/trailing\\"), "/media/trailing\\");
+    }
+
+    #[test]
+    fn finds_mount_source() {
+        let mountinfo = "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+                         98 22 8:17 / /media/user/KOBO\\040eReader rw,nosuid shared:50 - vfat \
+                         /dev/sdb1 rw\n";
+        assert_eq!(
+            find_mount_source(mountinfo, Path::new("/media/user/KOBO eReader")),
+            Some(PathBuf::from("/dev/sdb1"))
+        );
+        assert_eq!(find_mount_source(mountinfo, Path::new("/media/user")), None);
+    }
+}
@@ -1,23 +1,153 @@
 use std::error::Error;
 use std::io::{self, Write};
+use std::path::Path;
 
+use serde::Serialize;
 use tabwriter::TabWriter;
 
-pub fn run() -> Result<(), Box<dyn Error>> {
-    let available_devices = super::available_devices()?;
-    let mut tw = TabWriter::new(io::stdout());
-    write!(&mut tw, "\x1b[1mUID\tName\tManufacturer\x1b[0m\n").unwrap();
-    for device in available_devices {
-        // This is my lazy effort to generate a UID for each device. It's probably good enough, but
-        // could also probably be improved in the future.
-        let device_uid = device.usb_info.vendor_id() ^ device.usb_info.product_id();
-        write!(
-            &mut tw,
-            "{}\t{}\t{}\n",
-            device_uid, device.name, device.manufacturer
-        )
-        .unwrap();
+use super::super::common;
+use super::super::config::Config;
+use super::super::output::{self, Format};
+use super::{Capacity, Device};
+
+/// The representation of a device in machine-readable output. The field names are relied upon by
+/// scripts, so existing fields must not be renamed.
+#[derive(Serialize)]
+struct Record<'a> {
+    uid: &'a str,
+    name: &'a str,
+    friendly_name: Option<&'a str>,
+    manufacturer: &'a str,
+    vendor_id: u16,
+    product_id: u16,
+    mount_point: &'a Path,
+    total_bytes: Option<u64>,
+    free_bytes: Option<u64>,
+    serial: Option<&'a str>,
+    profile: Option<&'a str>,
+}
+
+/// The names of the fields of a record, in the order they are printed as delimited values.
+const RECORD_FIELDS: &[&str] = &[
+    "uid",
+    "name",
+    "friendly_name",
+    "manufacturer",
+    "vendor_id",
+    "product_id",
+    "mount_point",
+    "total_bytes",
+    "free_bytes",
+    "serial",
+    "profile",
+];
+
+impl<'a> Record<'a> {
+    fn new(device: &'a Device) -> Record<'a> {
+        let capacity = device.capacity().ok();
+        Record {
+            uid: device.uid(),
+            name: device.name(),
+            friendly_name: device.friendly_name(),
+            manufacturer: device.manufacturer(),
+            vendor_id: device.vendor_id(),
+            product_id: device.product_id(),
+            mount_point: device.mount_dir(),
+            total_bytes: capacity.map(|capacity| capacity.total),
+            free_bytes: capacity.map(|capacity| capacity.free),
+            serial: device.serial(),
+            profile: device.profile(),
+        }
+    }
+
+    /// Returns the values of the record in the order of `RECORD_FIELDS`.
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.uid.to_owned(),
+            self.name.to_owned(),
+            self.friendly_name.unwrap_or_default().to_owned(),
+            self.manufacturer.to_owned(),
+            self.vendor_id.to_string(),
+            self.product_id.to_string(),
+            self.mount_point.display().to_string(),
+            self.total_bytes.map(|n| n.to_string()).unwrap_or_default(),
+            self.free_bytes.map(|n| n.to_string()).unwrap_or_default(),
+            self.serial.unwrap_or_default().to_owned(),
+            self.profile.unwrap_or_default().to_owned(),
+        ]
+    }
+}
+
+/// Describes the capacity of a device for the table, such as `3.1 GB free of 7.9 GB`.
+fn describe_capacity(capacity: Option<Capacity>) -> String {
+    match capacity {
+        Some(capacity) => format!(
+            "{} free of {}",
+            common::format_size(capacity.free),
+            common::format_size(capacity.total)
+        ),
+        None => "unknown".to_owned(),
+    }
+}
+
+pub fn run(config: &Config, format: Format) -> Result<(), Box<dyn Error>> {
+    let available_devices = super::available_devices(config)?;
+    match format {
+        Format::Table => {
+            let mut tw = TabWriter::new(io::stdout());
+            writeln!(&mut tw, "\x1b[1mUID\tName\tManufacturer\tCapacity\x1b[0m").unwrap();
+            for device in available_devices {
+                writeln!(
+                    &mut tw,
+                    "{}\t{}\t{}\t{}",
+                    device.uid(),
+                    device.friendly_name().unwrap_or(device.name()),
+                    device.manufacturer,
+                    describe_capacity(device.capacity().ok())
+                )
+                .unwrap();
+            }
+            tw.flush().unwrap();
+            Ok(())
+        }
+        Format::Json => {
+            let records: Vec<Record> = available_devices.iter().map(Record::new).collect();
+            output::write_json(&records)
+        }
+        Format::Csv | Format::Tsv => {
+            let rows: Vec<Vec<String>> = available_devices
+                .iter()
+                .map(|device| Record::new(device).values())
+                .collect();
+            output::write_delimited(format, RECORD_FIELDS, &rows)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::device::mass_storage::MassStorage;
+
+    #[test]
+    fn record_values_match_fields() {
+        let usb_info = MassStorage::new(PathBuf::from("/media/READER"), 1, 2, "", &["epub"]);
+        let mut device = Device::new(
+            "Reader".to_owned(),
+            "Acme".to_owned(),
+            Box::new(usb_info),
+            Some("SN0001"),
+        );
+        device.profile = Some("my-reader".to_owned());
+        let values = Record::new(&device).values();
+        assert_eq!(values.len(), RECORD_FIELDS.len());
+
+        let value = |field: &str| &values[RECORD_FIELDS.iter().position(|f| *f == field).unwrap()];
+        assert_eq!(value("uid"), device.uid());
+        assert_eq!(value("mount_point"), "/media/READER");
+        assert_eq!(value("serial"), "SN0001");
+        assert_eq!(value("profile"), "my-reader");
     }
-    tw.flush().unwrap();
-    Ok(())
 }
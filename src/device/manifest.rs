@@ -0,0 +1,156 @@
+//! Record of the books libri has placed on a device.
+//!
+//! The manifest is stored on the device itself, so it stays accurate when the device is used with
+//! several computers. Books which are not listed in the manifest were put on the device by other
+//! means and are never modified or removed by libri.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Path of the manifest file, relative to the mount directory of the device.
+const MANIFEST_PATH: &str = ".libri/manifest.json";
+
+/// Version of the manifest file format.
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize)]
+struct ManifestFile {
+    version: u32,
+    books: Vec<Entry>,
+}
+
+/// A book placed on the device by libri.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    /// The unique identifier of the book.
+    pub identifier: String,
+    /// The path of the book, relative to the mount directory of the device.
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct Manifest {
+    mount_dir: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Reads the manifest of the device mounted at `mount_dir`. Devices libri has never written
+    /// to have an empty manifest.
+    pub fn open(mount_dir: &Path) -> Result<Manifest, Error> {
+        let path = mount_dir.join(MANIFEST_PATH);
+        let entries = if path.exists() {
+            let manifest: ManifestFile =
+                serde_json::from_reader(BufReader::new(File::open(&path)?)).map_err(|e| {
+                    Error::Device(format!("invalid manifest '{}': {}", path.display(), e))
+                })?;
+            if manifest.version != MANIFEST_VERSION {
+                return Err(Error::Device(format!(
+                    "manifest '{}' was written by an incompatible version of libri",
+                    path.display()
+                )));
+            }
+            manifest.books
+        } else {
+            Vec::new()
+        };
+        Ok(Manifest {
+            mount_dir: mount_dir.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Returns the books listed in the manifest.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the entry of the book with the given identifier, if libri placed it on the device.
+    pub fn get(&self, identifier: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.identifier == identifier)
+    }
+
+    /// Returns the absolute path of the file of an entry.
+    pub fn path_of(&self, entry: &Entry) -> PathBuf {
+        self.mount_dir.join(&entry.path)
+    }
+
+    /// Records that the book with the given identifier was placed at `path` on the device,
+    /// replacing any previous entry for the book.
+    pub fn insert(&mut self, identifier: &str, path: &Path) {
+        let path = path.strip_prefix(&self.mount_dir).unwrap_or(path);
+        self.remove(identifier);
+        self.entries.push(Entry {
+            identifier: identifier.to_owned(),
+            path: path.to_path_buf(),
+        });
+    }
+
+    /// Removes the entry of the book with the given identifier, returning it.
+    pub fn remove(&mut self, identifier: &str) -> Option<Entry> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.identifier == identifier)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// Writes the manifest to the device.
+    pub fn save(&self) -> Result<(), Error> {
+        let path = self.mount_dir.join(MANIFEST_PATH);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let manifest = ManifestFile {
+            version: MANIFEST_VERSION,
+            books: self.entries.clone(),
+        };
+
+        // Write to a temporary file first so an interrupted write (such as the device being
+        // unplugged) never leaves a truncated manifest behind.
+        let temp_path = path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer_pretty(&mut writer, &manifest).map_err(io::Error::from)?;
+        writer.flush()?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_open() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let mut manifest = Manifest::open(dir.path()).unwrap();
+        assert!(manifest.entries().is_empty());
+
+        manifest.insert("id-a", &dir.path().join("Author/Title.epub"));
+        manifest.insert("id-b", &dir.path().join("Other.epub"));
+        manifest.insert("id-a", &dir.path().join("Author/Renamed.epub"));
+        manifest.save().unwrap();
+
+        let manifest = Manifest::open(dir.path()).unwrap();
+        assert_eq!(manifest.entries().len(), 2);
+        let entry = manifest.get("id-a").unwrap();
+        assert_eq!(entry.path, PathBuf::from("Author/Renamed.epub"));
+        assert_eq!(
+            manifest.path_of(entry),
+            dir.path().join("Author/Renamed.epub")
+        );
+    }
+
+    #[test]
+    fn open_invalid_manifest() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".libri")).unwrap();
+        fs::write(dir.path().join(MANIFEST_PATH), "not json").unwrap();
+
+        assert!(matches!(Manifest::open(dir.path()), Err(Error::Device(_))));
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::super::config::DeviceConfig;
+use super::super::template::Template;
+use super::super::{common, Ebook};
+use super::registry::Model;
+use super::UsbDevice;
+
+/// An eReader which picks up any book copied to its book directory, such as a Kindle, a
+/// PocketBook or a device described by a profile in the configuration.
+pub struct MassStorage {
+    mount_dir: PathBuf,
+    vendor_id: u16,
+    product_id: u16,
+    /// The directory books are stored in, relative to the mount directory.
+    book_dir: PathBuf,
+    /// The file extensions of the formats the device can read.
+    formats: Vec<String>,
+}
+
+impl MassStorage {
+    pub fn new(
+        mount_dir: PathBuf,
+        vendor_id: u16,
+        product_id: u16,
+        book_dir: &str,
+        formats: &[impl AsRef<str>],
+    ) -> MassStorage {
+        MassStorage {
+            mount_dir,
+            vendor_id,
+            product_id,
+            book_dir: PathBuf::from(book_dir),
+            formats: formats
+                .iter()
+                .map(|format| format.as_ref().to_owned())
+                .collect(),
+        }
+    }
+
+    /// Creates a device of a model from the registry.
+    pub fn from_model(mount_dir: PathBuf, model: &Model) -> MassStorage {
+        MassStorage::new(
+            mount_dir,
+            model.vendor_id,
+            model.product_id,
+            model.book_dir,
+            model.formats,
+        )
+    }
+
+    /// Returns true if the device can read books with the given file extension.
+    fn supports(&self, extension: &str) -> bool {
+        self.formats
+            .iter()
+            .any(|format| format.eq_ignore_ascii_case(extension))
+    }
+}
+
+impl UsbDevice for MassStorage {
+    fn mount_dir(&self) -> &Path {
+        self.mount_dir.as_path()
+    }
+
+    fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    fn destination(
+        &self,
+        ebook: &Ebook,
+        template: &Template,
+        _settings: &DeviceConfig,
+    ) -> Result<PathBuf, io::Error> {
+        let extension = ebook
+            .path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        if !self.supports(extension) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("device can't read .{} books", extension),
+            ));
+        }
+
+        let relative = template
+            .render(ebook)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(self.mount_dir.join(&self.book_dir).join(relative))
+    }
+
+    fn upload_ebook(
+        &self,
+        ebook: &Ebook,
+        template: &Template,
+        settings: &DeviceConfig,
+        dry_run: bool,
+    ) -> Result<PathBuf, io::Error> {
+        let destination = self.destination(ebook, template, settings)?;
+        if destination.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "already on device",
+            ));
+        }
+
+        if !dry_run {
+            fs::create_dir_all(destination.parent().unwrap())?;
+            common::copy(&ebook.path, &destination)?;
+        }
+        Ok(destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::registry::{self, KINDLE_VENDOR_ID, POCKETBOOK_VENDOR_ID};
+
+    #[test]
+    fn supported_formats() {
+        let model = registry::find(KINDLE_VENDOR_ID, 0x0004).unwrap();
+        let kindle = MassStorage::from_model(PathBuf::from("/kindle"), model);
+        assert!(kindle.supports("MOBI"));
+        assert!(!kindle.supports("epub"));
+
+        let model = registry::find(POCKETBOOK_VENDOR_ID, 0x0001).unwrap();
+        let pocketbook = MassStorage::from_model(PathBuf::from("/pocketbook"), model);
+        assert!(pocketbook.supports("epub"));
+    }
+}
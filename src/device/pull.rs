@@ -0,0 +1,71 @@
+//! Imports books found on a device which are missing from the library.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use super::super::catalog::Catalog;
+use super::super::format::epub;
+use super::super::{common, config, import};
+
+/// Returns the paths of the ebooks stored below `mount_dir` which are not in the catalog. Books
+/// which fail to parse are kept so the import reports them as failures.
+fn missing_ebooks(mount_dir: &Path, catalog: &Catalog) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = common::find_ebooks(mount_dir)?;
+    paths.sort();
+    paths.retain(|path| match epub::parse(path) {
+        Ok(ebook) => catalog.find(&ebook.identifier).is_none(),
+        Err(_) => true,
+    });
+    Ok(paths)
+}
+
+pub fn run(
+    config: &config::Config,
+    uid: &str,
+    move_books: bool,
+    dry_run: bool,
+    fail_fast: bool,
+) -> Result<(), Box<dyn Error>> {
+    let device = super::find_device(config, uid)?;
+    let catalog = Catalog::open(&config.library)?;
+    let paths = missing_ebooks(device.mount_dir(), &catalog)?;
+    if paths.is_empty() {
+        println!("every book on the device is already in the library");
+        return Ok(());
+    }
+    import::import_paths(config, paths, move_books, dry_run, fail_fast)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::fixtures::write_epub;
+
+    #[test]
+    fn missing_ebooks_skips_library_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let library = dir.path().join("library");
+        let mount = dir.path().join("device");
+        fs::create_dir_all(&library).unwrap();
+        fs::create_dir_all(&mount).unwrap();
+
+        write_epub(&library.join("emma.epub"), "id-emma", "Emma", "Jane Austen");
+        write_epub(&mount.join("emma.epub"), "id-emma", "Emma", "Jane Austen");
+        write_epub(
+            &mount.join("dracula.epub"),
+            "id-dracula",
+            "Dracula",
+            "Bram Stoker",
+        );
+        fs::write(mount.join("broken.epub"), "not an epub").unwrap();
+
+        let catalog = Catalog::open(&library).unwrap();
+        let paths = missing_ebooks(&mount, &catalog).unwrap();
+        assert_eq!(
+            paths,
+            [mount.join("broken.epub"), mount.join("dracula.epub")]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! The eReader models libri recognizes, keyed by their USB vendor and product IDs.
+
+pub const KOBO_VENDOR_ID: u16 = 0x2237;
+pub const KINDLE_VENDOR_ID: u16 = 0x1949;
+pub const POCKETBOOK_VENDOR_ID: u16 = 0xfffe;
+
+/// The family of a model, which decides how libri talks to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    Kobo,
+    /// Models which pick up any book copied to their book directory, such as Kindles and
+    /// PocketBooks.
+    MassStorage,
+}
+
+/// An eReader model which mounts as USB mass storage.
+#[derive(Debug, PartialEq)]
+pub struct Model {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub family: Family,
+    pub name: &'static str,
+    /// The directory books are stored in, relative to the mount directory.
+    pub book_dir: &'static str,
+    /// The file extensions of the formats the model can read.
+    pub formats: &'static [&'static str],
+}
+
+const KOBO_FORMATS: &[&str] = &["epub", "pdf", "txt", "cbz"];
+// Kindles can't read EPUB, so books have to be converted to one of these formats before they
+// can be uploaded to a Kindle. libri doesn't convert books for Kindles yet.
+const KINDLE_FORMATS: &[&str] = &["azw3", "azw", "mobi", "pdf", "txt"];
+const POCKETBOOK_FORMATS: &[&str] = &["epub", "pdf", "fb2", "mobi", "djvu", "txt", "cbz"];
+
+const fn kobo(product_id: u16, name: &'static str) -> Model {
+    Model {
+        vendor_id: KOBO_VENDOR_ID,
+        product_id,
+        family: Family::Kobo,
+        name,
+        book_dir: "",
+        formats: KOBO_FORMATS,
+    }
+}
+
+const fn kindle(product_id: u16, name: &'static str) -> Model {
+    Model {
+        vendor_id: KINDLE_VENDOR_ID,
+        product_id,
+        family: Family::MassStorage,
+        name,
+        book_dir: "documents",
+        formats: KINDLE_FORMATS,
+    }
+}
+
+const fn pocketbook(product_id: u16, name: &'static str) -> Model {
+    Model {
+        vendor_id: POCKETBOOK_VENDOR_ID,
+        product_id,
+        family: Family::MassStorage,
+        name,
+        book_dir: "Books",
+        formats: POCKETBOOK_FORMATS,
+    }
+}
+
+/// Every model libri recognizes.
+pub const MODELS: &[Model] = &[
+    kobo(0x4228, "Kobo Clara HD"),
+    kobo(0x4231, "Kobo Sage"),
+    kobo(0x4232, "Kobo Libra H2O"),
+    kobo(0x4233, "Kobo Elipsa"),
+    kobo(0x4234, "Kobo Libra 2"),
+    kobo(0x4235, "Kobo Clara 2E"),
+    // Kindles which mount as USB mass storage share a product ID, listed as "Amazon Kindle
+    // 3/4/Paperwhite" in the USB ID Repository (http://www.linux-usb.org/usb.ids). Newer models
+    // only support MTP.
+    kindle(0x0004, "Kindle"),
+    // PocketBook readers all report the same IDs.
+    pocketbook(0x0001, "PocketBook"),
+];
+
+/// Returns the model with the given USB vendor and product IDs.
+pub fn find(vendor_id: u16, product_id: u16) -> Option<&'static Model> {
+    MODELS
+        .iter()
+        .find(|model| model.vendor_id == vendor_id && model.product_id == product_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_are_unique() {
+        for (i, model) in MODELS.iter().enumerate() {
+            assert_eq!(find(model.vendor_id, model.product_id), Some(model));
+            assert!(!MODELS[..i].iter().any(|other| other.name == model.name));
+        }
+    }
+}
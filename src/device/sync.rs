@@ -0,0 +1,271 @@
+//! Keeps the books on a device in line with a selection of books from the library.
+//!
+//! Only books listed in the manifest of the device are ever removed, so books put on the device
+//! by other means are left alone.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use super::super::{common, config, list, upload, Ebook};
+use super::Device;
+
+#[derive(Default)]
+struct SyncStats {
+    copied: u32,
+    removed: u32,
+    unchanged: u32,
+    skipped: u32,
+    elapsed: Duration,
+}
+
+impl fmt::Display for SyncStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "copied {}; removed {}; unchanged {}; skipped {}; finished in {:.2}s",
+            self.copied,
+            self.removed,
+            self.unchanged,
+            self.skipped,
+            self.elapsed.as_secs_f32()
+        )
+    }
+}
+
+/// Options controlling which books are kept on which devices.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Books to keep on the device, given as identifiers of books and the words of a query. Every
+    /// book in the library is selected when empty.
+    pub selectors: Vec<String>,
+    /// Only sync the device with this UID.
+    pub device: Option<String>,
+    /// Only keep the given number of most recently imported books out of the selection.
+    pub latest: Option<usize>,
+    /// Remove books libri placed on the device which are no longer selected.
+    pub delete: bool,
+    pub dry_run: bool,
+}
+
+/// Copies the selected ebooks missing from the device and, when `delete` is set, removes the books
+/// libri placed on the device earlier which are no longer selected.
+fn sync_device(
+    device: &Device,
+    ebooks: &[Ebook],
+    config: &config::Config,
+    delete: bool,
+    dry_run: bool,
+    stats: &mut SyncStats,
+) -> Result<(), Box<dyn Error>> {
+    let mut manifest = device.manifest()?;
+
+    for ebook in ebooks {
+        if let Some(entry) = manifest.get(&ebook.identifier) {
+            if manifest.path_of(entry).exists() {
+                stats.unchanged += 1;
+                continue;
+            }
+        }
+        match device.upload_ebook(ebook, config, dry_run) {
+            Ok(path) => {
+                stats.copied += 1;
+                manifest.insert(&ebook.identifier, &path);
+                println!("copied \"{}\"", ebook.title);
+            }
+            Err(e) => {
+                stats.skipped += 1;
+                println!("skipping \"{}\" -- {}", ebook.title, e);
+            }
+        }
+    }
+
+    let selected: HashSet<&str> = ebooks.iter().map(|e| e.identifier.as_str()).collect();
+    let stale: Vec<_> = manifest
+        .entries()
+        .iter()
+        .filter(|entry| !selected.contains(entry.identifier.as_str()))
+        .cloned()
+        .collect();
+    if delete {
+        for entry in stale {
+            let path = manifest.path_of(&entry);
+            if !dry_run && path.exists() {
+                // Keep going, so the manifest still records the books removed so far.
+                if let Err(e) = fs::remove_file(&path) {
+                    stats.skipped += 1;
+                    println!("skipping '{}' -- {}", entry.path.display(), e);
+                    continue;
+                }
+                // The book is gone either way, so leftover directories aren't worth failing over.
+                let _ = common::remove_empty_dirs(path.parent().unwrap(), device.mount_dir());
+            }
+            manifest.remove(&entry.identifier);
+            stats.removed += 1;
+            println!("removed '{}'", entry.path.display());
+        }
+    } else if !stale.is_empty() {
+        println!(
+            "{} books on the device are no longer selected; use --delete to remove them",
+            stale.len()
+        );
+    }
+
+    if !dry_run {
+        manifest.save()?;
+    }
+    Ok(())
+}
+
+pub fn run(config: &config::Config, options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut stats = SyncStats::default();
+    let start = Instant::now();
+
+    let ebooks = upload::select_ebooks(
+        list::get_ebooks(&config.library)?,
+        &options.selectors,
+        options.latest,
+    )?;
+    let available_devices = match &options.device {
+        Some(uid) => vec![super::find_device(config, uid)?],
+        None => super::available_devices(config)?,
+    };
+    if available_devices.is_empty() {
+        println!("no devices available");
+        return Ok(());
+    }
+    for device in &available_devices {
+        println!("{}", device.name());
+        sync_device(
+            device,
+            &ebooks,
+            config,
+            options.delete,
+            options.dry_run,
+            &mut stats,
+        )?;
+        println!();
+    }
+    stats.elapsed = start.elapsed();
+    print!("{}", stats);
+    if options.dry_run {
+        print!("; dry run");
+    }
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use super::super::kobo::Kobo;
+    use super::super::registry::{self, KOBO_VENDOR_ID};
+    use super::*;
+    use crate::fixtures::EbookBuilder;
+    use crate::template::Template;
+
+    fn ebook(library: &Path, identifier: &str, title: &str) -> Ebook {
+        let path = library.join(format!("{}.epub", title));
+        fs::write(&path, title).unwrap();
+        EbookBuilder::new(title, "Jane Austen")
+            .identifier(identifier)
+            .path(&path)
+            .build()
+    }
+
+    #[test]
+    fn sync_copies_and_removes_managed_books() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let library = dir.path().join("library");
+        let mount = dir.path().join("device");
+        fs::create_dir_all(&library).unwrap();
+        fs::create_dir_all(&mount).unwrap();
+        let device = Device::new(
+            "Libra 2".to_owned(),
+            "Kobo".to_owned(),
+            Box::new(Kobo::new(
+                mount.clone(),
+                registry::find(KOBO_VENDOR_ID, 0x4234).unwrap(),
+            )),
+            None,
+        );
+        let config = config::Config {
+            library: library.clone(),
+            path_template: Template::default(),
+            devices: HashMap::new(),
+            device_names: HashMap::new(),
+        };
+        let emma = ebook(&library, "urn:uuid:1", "Emma");
+        let persuasion = ebook(&library, "urn:uuid:2", "Persuasion");
+        // A book copied to the device by hand, which sync must never touch.
+        fs::write(mount.join("Other.epub"), "other").unwrap();
+
+        let mut stats = SyncStats::default();
+        let ebooks = [emma, persuasion];
+        sync_device(&device, &ebooks, &config, true, false, &mut stats).unwrap();
+        assert_eq!((stats.copied, stats.removed), (2, 0));
+        assert!(mount
+            .join("Jane Austen/Persuasion/Persuasion.epub")
+            .exists());
+
+        let mut stats = SyncStats::default();
+        sync_device(&device, &ebooks[..1], &config, false, false, &mut stats).unwrap();
+        assert_eq!((stats.unchanged, stats.removed), (1, 0));
+        assert!(mount
+            .join("Jane Austen/Persuasion/Persuasion.epub")
+            .exists());
+
+        let mut stats = SyncStats::default();
+        sync_device(&device, &ebooks[..1], &config, true, false, &mut stats).unwrap();
+        assert_eq!((stats.unchanged, stats.removed), (1, 1));
+        assert!(!mount.join("Jane Austen/Persuasion").exists());
+        assert!(mount.join("Jane Austen/Emma/Emma.epub").exists());
+        assert!(mount.join("Other.epub").exists());
+        assert_eq!(device.manifest().unwrap().entries().len(), 1);
+    }
+
+    #[test]
+    fn sync_keeps_going_when_a_book_cant_be_removed() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let library = dir.path().join("library");
+        let mount = dir.path().join("device");
+        fs::create_dir_all(&library).unwrap();
+        fs::create_dir_all(&mount).unwrap();
+        let device = Device::new(
+            "Libra 2".to_owned(),
+            "Kobo".to_owned(),
+            Box::new(Kobo::new(
+                mount.clone(),
+                registry::find(KOBO_VENDOR_ID, 0x4234).unwrap(),
+            )),
+            None,
+        );
+        let config = config::Config {
+            library: library.clone(),
+            path_template: Template::parse("{title}.{ext}").unwrap(),
+            devices: HashMap::new(),
+            device_names: HashMap::new(),
+        };
+        let ebooks = [
+            ebook(&library, "urn:uuid:1", "Emma"),
+            ebook(&library, "urn:uuid:2", "Persuasion"),
+        ];
+        let mut stats = SyncStats::default();
+        sync_device(&device, &ebooks, &config, true, false, &mut stats).unwrap();
+
+        // Removing a file fails when a directory has taken its place.
+        fs::remove_file(mount.join("Emma.epub")).unwrap();
+        fs::create_dir(mount.join("Emma.epub")).unwrap();
+        let mut stats = SyncStats::default();
+        sync_device(&device, &[], &config, true, false, &mut stats).unwrap();
+        assert_eq!((stats.skipped, stats.removed), (1, 1));
+        assert!(!mount.join("Persuasion.epub").exists());
+        let manifest = device.manifest().unwrap();
+        assert_eq!(manifest.entries().len(), 1);
+        assert_eq!(manifest.entries()[0].identifier, "urn:uuid:1");
+    }
+}
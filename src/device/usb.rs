@@ -1,8 +1,11 @@
 use std::fmt;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use super::super::Ebook;
+use super::super::config::DeviceConfig;
+use super::super::template::Template;
+use super::super::{Ebook, Error};
+use super::annotations::ReadingState;
 
 /// Types that implement this trait represent physical USB eReader hardware connected to the computer.
 pub trait UsbDevice {
@@ -14,9 +17,33 @@ pub trait UsbDevice {
     /// Returns the product ID of the USB device.
     fn product_id(&self) -> u16;
 
+    /// Returns the path the specified ebook is uploaded to, with the same arguments as
+    /// `upload_ebook`. Returns an error if the device can't read the book.
+    fn destination(
+        &self,
+        ebook: &Ebook,
+        template: &Template,
+        settings: &DeviceConfig,
+    ) -> Result<PathBuf, io::Error>;
+
     /// Uploads the specified ebook to the correct location on the device such that it will be
-    /// recognized automatically.
-    fn upload_ebook(&self, ebook: &Ebook, library: &Path, dry_run: bool) -> Result<(), io::Error>;
+    /// recognized automatically. The location of the book on the device is described by
+    /// `template`, relative to the mount directory, and `settings` holds the configuration of the
+    /// device. Returns the path of the uploaded book.
+    fn upload_ebook(
+        &self,
+        ebook: &Ebook,
+        template: &Template,
+        settings: &DeviceConfig,
+        dry_run: bool,
+    ) -> Result<PathBuf, io::Error>;
+
+    /// Returns the reading progress and annotations of the books on the device.
+    fn reading_states(&self) -> Result<Vec<ReadingState>, Error> {
+        Err(Error::Device(
+            "reading progress is not supported by this device".to_owned(),
+        ))
+    }
 }
 
 impl fmt::Debug for dyn UsbDevice {
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::io;
+
+use zip::result::ZipError;
+
+/// Errors that can occur while managing the library or interacting with devices.
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O operation failed.
+    Io(io::Error),
+    /// An ebook archive could not be read.
+    Zip(ZipError),
+    /// A metadata document could not be parsed.
+    Xml(::xml::reader::Error),
+    /// A required metadata field is missing from an ebook. Contains a description of the field.
+    MissingMetadata(String),
+    /// The configuration could not be read or contains an invalid value.
+    InvalidConfig(String),
+    /// A device could not be recognized or accessed.
+    Device(String),
+    /// A database stored on a device could not be read.
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Zip(e) => write!(f, "invalid ebook archive: {}", e),
+            Error::Xml(e) => write!(f, "invalid metadata: {}", e),
+            Error::MissingMetadata(field) => write!(f, "ebook metadata missing {}", field),
+            Error::InvalidConfig(message) => write!(f, "invalid configuration: {}", message),
+            Error::Device(message) => write!(f, "device error: {}", message),
+            Error::Database(e) => write!(f, "invalid device database: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Zip(e) => Some(e),
+            Error::Xml(e) => Some(e),
+            Error::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(error: ZipError) -> Error {
+        Error::Zip(error)
+    }
+}
+
+impl From<::xml::reader::Error> for Error {
+    fn from(error: ::xml::reader::Error) -> Error {
+        Error::Xml(error)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Error {
+        Error::Database(error)
+    }
+}
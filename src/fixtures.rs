@@ -0,0 +1,99 @@
+//! Fixtures shared by the unit tests.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{TimeZone, Utc};
+use zip::write::{FileOptions, ZipWriter};
+
+use crate::{Contributor, Ebook};
+
+/// Builds an `Ebook` by the given author stored at `/library/book.epub`, with placeholders for
+/// anything not set.
+pub struct EbookBuilder {
+    ebook: Ebook,
+}
+
+impl EbookBuilder {
+    pub fn new(title: &str, author: &str) -> EbookBuilder {
+        EbookBuilder {
+            ebook: Ebook::new(
+                "id".to_owned(),
+                title.to_owned(),
+                vec![Contributor::new(author.to_owned(), None, None)],
+                Path::new("/library/book.epub"),
+            ),
+        }
+    }
+
+    pub fn identifier(mut self, identifier: &str) -> EbookBuilder {
+        self.ebook.identifier = identifier.to_owned();
+        self
+    }
+
+    pub fn path(mut self, path: &Path) -> EbookBuilder {
+        self.ebook.path = path.to_path_buf();
+        self
+    }
+
+    /// Sets the import date to noon UTC on the given day.
+    pub fn imported(mut self, year: i32, month: u32, day: u32) -> EbookBuilder {
+        self.ebook.imported = Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap();
+        self
+    }
+
+    pub fn build(self) -> Ebook {
+        self.ebook
+    }
+}
+
+/// Writes a minimal EPUB containing the given metadata to `path`.
+pub fn write_epub(path: &Path, identifier: &str, title: &str, author: &str) {
+    write_epub_with_opf(path, &opf(identifier, title, author, ""));
+}
+
+/// Writes an EPUB whose package document is `opf` to `path`.
+pub fn write_epub_with_opf(path: &Path, opf: &str) {
+    write_epub_with_files(path, opf, &[]);
+}
+
+/// Writes an EPUB whose package document is `opf` to `path`, along with additional files given
+/// as pairs of paths inside the archive and contents.
+pub fn write_epub_with_files(path: &Path, opf: &str, files: &[(&str, &[u8])]) {
+    let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    let options = FileOptions::default();
+    zip.start_file("mimetype", options).unwrap();
+    zip.write_all(b"application/epub+zip").unwrap();
+    zip.start_file("META-INF/container.xml", options).unwrap();
+    zip.write_all(container.as_bytes()).unwrap();
+    zip.start_file("OEBPS/content.opf", options).unwrap();
+    zip.write_all(opf.as_bytes()).unwrap();
+    for (name, contents) in files {
+        zip.start_file(*name, options).unwrap();
+        zip.write_all(contents).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn opf(identifier: &str, title: &str, author: &str, manifest: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:creator>{}</dc:creator>
+  </metadata>
+  {}
+</package>"#,
+        identifier, title, author, manifest
+    )
+}
@@ -1 +1,2 @@
 pub mod epub;
+pub mod kepub;
@@ -1,104 +1,728 @@
-use std::error::Error;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::{Path, PathBuf};
-use zip::{result, ZipArchive};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::ZipArchive;
 
 use crate::xml;
-use crate::Ebook;
+use crate::{Contributor, Ebook, Error, Identifier, Series};
 
-pub fn parse(path: &Path) -> Result<Ebook, Box<dyn Error>> {
+pub fn parse(path: &Path) -> Result<Ebook, Error> {
     let mut archive = ZipArchive::new(File::open(path)?)?;
     let metadata_path = get_metadata_path(&mut archive)?;
-    let document = parse_metadata(&mut archive, metadata_path.as_path())?;
+    let document = parse_metadata(&mut archive, &metadata_path)?;
 
-    let unique_identifier_attribute = document
-        .elements
-        .iter()
-        .find(|x| x.tag == "package")
-        .unwrap()
-        .attributes
-        .get("unique-identifier")
-        .expect("epub metadata missing unique identifier attribute");
-
-    Ok(Ebook::new(
-        document
-            .elements
-            .iter()
-            .find(|x| {
-                x.prefix.as_deref() == Some("dc")
-                    && x.tag == "identifier"
-                    && x.attributes.get("id").unwrap() == unique_identifier_attribute
-            })
-            .expect("epub metadata missing unique identifier")
-            .content
-            .clone(),
-        document
-            .elements
-            .iter()
-            .find(|x| x.prefix.as_deref() == Some("dc") && x.tag == "title")
-            .expect("epub metadata missing title")
-            .content
-            .clone(),
-        document
-            .elements
+    let package = document
+        .find("package")
+        .ok_or_else(|| missing("package element"))?;
+    let unique_identifier_attribute = package
+        .attribute("unique-identifier")
+        .ok_or_else(|| missing("unique identifier attribute"))?;
+    let metadata = package
+        .find("metadata")
+        .ok_or_else(|| missing("metadata element"))?;
+
+    // Returns an iterator over the Dublin Core elements with the given tag.
+    let dc = |tag: &str| metadata.find_all(&format!("dc:{}", tag)).into_iter();
+    let text = |element: &xml::XmlElement| element.text().to_owned();
+    let refinements = Refinements::new(metadata);
+
+    let creators: Vec<Contributor> = dc("creator")
+        .map(|x| contributor(x, &refinements))
+        .collect();
+    if creators.is_empty() {
+        return Err(missing("author"));
+    }
+
+    // EPUB 3 books can contain several titles, distinguished by their title type.
+    let titles: Vec<&xml::XmlElement> = dc("title").collect();
+    let title_of_type = |title_type: &str| {
+        titles
             .iter()
-            .find(|x| x.prefix.as_deref() == Some("dc") && x.tag == "creator")
-            .expect("epub metadata missing author")
-            .content
-            .clone(),
+            .find(|x| refinements.get(x, "title-type") == Some(title_type))
+            .copied()
+    };
+    let main_title = title_of_type("main")
+        .or_else(|| titles.first().copied())
+        .ok_or_else(|| missing("title"))?;
+
+    let mut ebook = Ebook::new(
+        dc("identifier")
+            .find(|x| x.attribute("id") == Some(unique_identifier_attribute))
+            .map(text)
+            .ok_or_else(|| missing("unique identifier"))?,
+        text(main_title),
+        creators,
         path,
-    ))
+    );
+    ebook.subtitle = title_of_type("subtitle").map(text);
+    ebook.title_sort = refinements
+        .get(main_title, "file-as")
+        .or_else(|| meta_content(metadata, "calibre:title_sort"))
+        .map(str::to_owned);
+    ebook.contributors = dc("contributor")
+        .map(|x| contributor(x, &refinements))
+        .collect();
+    ebook.identifiers = dc("identifier")
+        .map(|x| identifier(x, &refinements))
+        .collect();
+    ebook.languages = dc("language").map(text).collect();
+    ebook.publisher = dc("publisher").next().map(text);
+    // EPUB 2 books may list several dates for different events (creation, modification, ...).
+    // Prefer the publication date when present.
+    ebook.date = dc("date")
+        .find(|x| {
+            x.attribute("event")
+                .is_none_or(|event| event == "publication")
+        })
+        .or_else(|| dc("date").next())
+        .map(text);
+    ebook.description = dc("description").next().map(text);
+    ebook.subjects = dc("subject").map(text).collect();
+    ebook.rights = dc("rights").next().map(text);
+    ebook.series = series(metadata, &refinements);
+    ebook.version = package.attribute("version").unwrap_or_default().to_owned();
+    ebook.set_file_info(&path.metadata()?)?;
+    Ok(ebook)
 }
 
-fn get_metadata_path<R: Read + Seek>(archive: &mut ZipArchive<R>) -> result::ZipResult<PathBuf> {
-    let mut container = archive.by_name("META-INF/container.xml")?;
+fn missing(field: &str) -> Error {
+    Error::MissingMetadata(field.to_owned())
+}
 
-    // TODO: Add tests for this case
-    if container.enclosed_name() == None {
-        panic!("failed to read epub metadata");
+/// Metadata expressed through EPUB 3 `<meta refines="#id" property="...">` elements, indexed by
+/// the id of the element they refine.
+struct Refinements<'a> {
+    properties: HashMap<&'a str, Vec<(&'a str, &'a str)>>,
+}
+
+impl<'a> Refinements<'a> {
+    fn new(metadata: &'a xml::XmlElement) -> Refinements<'a> {
+        let mut properties: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+        for element in metadata.find_all("meta") {
+            if let (Some(refines), Some(property)) =
+                (element.attribute("refines"), element.attribute("property"))
+            {
+                properties
+                    .entry(refines.trim_start_matches('#'))
+                    .or_default()
+                    .push((property, element.text()));
+            }
+        }
+        Refinements { properties }
     }
 
-    let mut contents = String::new();
-    container.read_to_string(&mut contents)?;
+    /// Returns the value of the given property refining `element`.
+    fn get(&self, element: &xml::XmlElement, property: &str) -> Option<&'a str> {
+        self.properties
+            .get(element.attribute("id")?)?
+            .iter()
+            .find(|(name, _)| *name == property)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Returns the content of the EPUB 2 style `<meta name="..." content="..."/>` element with the
+/// given name. Calibre stores its custom metadata this way.
+fn meta_content<'a>(metadata: &'a xml::XmlElement, name: &str) -> Option<&'a str> {
+    metadata
+        .find_all("meta")
+        .into_iter()
+        .find(|x| x.attribute("name") == Some(name))
+        .and_then(|x| x.attribute("content"))
+}
+
+/// Returns the series the book belongs to. EPUB 3 books express series as collections, while
+/// books produced by Calibre use custom `calibre:series` metadata.
+fn series(metadata: &xml::XmlElement, refinements: &Refinements) -> Option<Series> {
+    let collection = metadata.find_all("meta").into_iter().find(|x| {
+        x.attribute("refines").is_none()
+            && x.attribute("property") == Some("belongs-to-collection")
+            && refinements
+                .get(x, "collection-type")
+                .is_none_or(|collection_type| collection_type == "series")
+    });
+    if let Some(collection) = collection {
+        return Some(Series::new(
+            collection.text().to_owned(),
+            refinements
+                .get(collection, "group-position")
+                .and_then(|position| position.parse().ok()),
+        ));
+    }
+
+    meta_content(metadata, "calibre:series").map(|name| {
+        Series::new(
+            name.to_owned(),
+            meta_content(metadata, "calibre:series_index").and_then(|index| index.parse().ok()),
+        )
+    })
+}
+
+/// Builds a contributor from a `dc:creator` or `dc:contributor` element. EPUB 2 books store the
+/// role and sort name in `opf:role` and `opf:file-as` attributes, while EPUB 3 books refine the
+/// element with `role` and `file-as` properties.
+fn contributor(element: &xml::XmlElement, refinements: &Refinements) -> Contributor {
+    let attribute_or_refinement = |name: &str| {
+        element
+            .attribute(name)
+            .or_else(|| refinements.get(element, name))
+            .map(str::to_owned)
+    };
+    Contributor::new(
+        element.text().to_owned(),
+        attribute_or_refinement("role"),
+        attribute_or_refinement("file-as"),
+    )
+}
 
-    let document = match xml::parse(&contents) {
-        Ok(document) => document,
-        // TODO: Add tests for this case
-        Err(error) => panic!("a problem occurred while parsing metadata: {}", error),
+/// Builds an identifier from a `dc:identifier` element. The scheme is read from the EPUB 2
+/// `opf:scheme` attribute or the EPUB 3 `identifier-type` property, or otherwise inferred from a
+/// URN prefix such as `urn:isbn:`.
+fn identifier(element: &xml::XmlElement, refinements: &Refinements) -> Identifier {
+    let value = element.text().to_owned();
+    let scheme = element
+        .attribute("scheme")
+        .or_else(|| refinements.get(element, "identifier-type"))
+        .map(|scheme| match scheme {
+            // ONIX code list 5 values, which EPUB 3 books commonly use for identifier types.
+            "02" | "15" => "ISBN".to_owned(),
+            "06" => "DOI".to_owned(),
+            scheme => scheme.to_uppercase(),
+        })
+        .or_else(|| identifier_scheme(&value).map(str::to_owned));
+    Identifier::new(scheme, value)
+}
+
+/// Infers the scheme of an identifier from its prefix.
+fn identifier_scheme(value: &str) -> Option<&'static str> {
+    let value = value.to_lowercase();
+    [
+        ("urn:isbn:", "ISBN"),
+        ("isbn:", "ISBN"),
+        ("urn:uuid:", "UUID"),
+        ("urn:doi:", "DOI"),
+        ("doi:", "DOI"),
+    ]
+    .iter()
+    .find(|(prefix, _)| value.starts_with(prefix))
+    .map(|(_, scheme)| *scheme)
+}
+
+/// A cover image stored inside an ebook.
+pub struct Cover {
+    /// The file extension matching the format of the image, such as `jpg`.
+    pub extension: String,
+    pub data: Vec<u8>,
+}
+
+/// Returns the cover image of the ebook at `path`, if it has one.
+///
+/// The cover is found using the EPUB 3 `cover-image` manifest property, the EPUB 2 `cover` meta
+/// element, or the cover reference in the guide, in that order. Guide references usually point to
+/// an XHTML page, in which case the first image on the page is used.
+pub fn cover(path: &Path) -> Result<Option<Cover>, Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let metadata_path = get_metadata_path(&mut archive)?;
+    let document = parse_metadata(&mut archive, &metadata_path)?;
+    let package = document
+        .find("package")
+        .ok_or_else(|| missing("package element"))?;
+    let items = package.find_all("manifest/item");
+
+    let item = items
+        .iter()
+        .find(|x| {
+            x.attribute("properties")
+                .is_some_and(|properties| properties.split_whitespace().any(|p| p == "cover-image"))
+        })
+        .or_else(|| {
+            let id = package
+                .find_all("metadata/meta")
+                .into_iter()
+                .find(|x| x.attribute("name") == Some("cover"))?
+                .attribute("content")?;
+            items.iter().find(|x| x.attribute("id") == Some(id))
+        });
+    let (image_path, media_type) = match item.and_then(|x| x.attribute("href")) {
+        Some(href) => (
+            resolve(&metadata_path, href),
+            item.and_then(|x| x.attribute("media-type")),
+        ),
+        None => {
+            let href = match package
+                .find_all("guide/reference")
+                .into_iter()
+                .find(|x| {
+                    x.attribute("type")
+                        .is_some_and(|kind| kind.eq_ignore_ascii_case("cover"))
+                })
+                .and_then(|x| x.attribute("href"))
+            {
+                Some(href) => href,
+                None => return Ok(None),
+            };
+            let target = resolve(&metadata_path, href);
+            let media_type = items
+                .iter()
+                .find(|x| {
+                    x.attribute("href")
+                        .map(|href| resolve(&metadata_path, href))
+                        == Some(target.clone())
+                })
+                .and_then(|x| x.attribute("media-type"));
+            if media_type.is_some_and(|media_type| media_type.starts_with("image/")) {
+                (target, media_type)
+            } else {
+                match page_image(&mut archive, &target)? {
+                    Some(image_path) => (image_path, None),
+                    None => return Ok(None),
+                }
+            }
+        }
     };
-    let element = document.elements.iter().find(|x| x.tag == "rootfile");
-
-    match element {
-        Some(element) => {
-            match element.attributes.get("full-path") {
-                Some(path) => Ok(PathBuf::from(path)),
-                // TODO: Add tests for this case
-                None => panic!("rootfile tag missing full-path attribute"),
+
+    Ok(Some(Cover {
+        extension: image_extension(media_type, &image_path),
+        data: read_file(&mut archive, &image_path)?,
+    }))
+}
+
+/// Returns the path of the first image shown on the XHTML page at `page_path`.
+fn page_image<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    page_path: &str,
+) -> Result<Option<String>, Error> {
+    let contents = String::from_utf8_lossy(&read_file(archive, page_path)?).into_owned();
+    let document = xml::parse(&contents)?;
+    Ok(document
+        .root
+        .descendants()
+        .into_iter()
+        .find_map(|x| match x.tag.as_str() {
+            "img" => x.attribute("src"),
+            // SVG images reference their source with an `xlink:href` attribute.
+            "image" => x.attribute("href"),
+            _ => None,
+        })
+        .map(|href| resolve(page_path, href)))
+}
+
+/// Returns the file extension for an image, based on its media type or otherwise its path.
+fn image_extension(media_type: Option<&str>, path: &str) -> String {
+    match media_type {
+        Some("image/jpeg") => "jpg".to_owned(),
+        Some("image/png") => "png".to_owned(),
+        Some("image/gif") => "gif".to_owned(),
+        Some("image/svg+xml") => "svg".to_owned(),
+        Some("image/webp") => "webp".to_owned(),
+        _ => match path.rsplit_once('.') {
+            Some((_, extension)) if !extension.contains('/') => extension.to_lowercase(),
+            _ => "jpg".to_owned(),
+        },
+    }
+}
+
+/// Resolves `href`, relative to the file at `base` inside the archive, to a path inside the
+/// archive. Fragments are removed and percent-encoded characters are decoded.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut components: Vec<&str> = match base.rsplit_once('/') {
+        Some((dir, _)) if !href.starts_with('/') => dir.split('/').collect(),
+        _ => Vec::new(),
+    };
+    let decoded = percent_decode(href);
+    for component in decoded.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
             }
+            component => components.push(component),
         }
-        None => {
-            // TODO: Add tests for this case
-            panic!("epub contains improperly formatted metadata file")
+    }
+    components.join("/")
+}
+
+/// Decodes percent-encoded characters, such as `%20`, in a URL path.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
         }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Reads the file at `name` inside the archive.
+fn read_file<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Vec<u8>, Error> {
+    let mut file = archive.by_name(name)?;
+    if file.enclosed_name().is_none() {
+        return Err(ZipError::InvalidArchive("invalid file path").into());
+    }
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Returns the path of the package document (the OPF file) inside the archive, as listed in the
+/// container file.
+fn get_metadata_path<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<String, Error> {
+    let mut container = archive.by_name("META-INF/container.xml")?;
+
+    if container.enclosed_name().is_none() {
+        return Err(ZipError::InvalidArchive("invalid container path").into());
+    }
+
+    let mut contents = String::new();
+    container.read_to_string(&mut contents)?;
+
+    let document = xml::parse(&contents)?;
+    let element = document
+        .find("container/rootfiles/rootfile")
+        .ok_or_else(|| missing("rootfile element in container"))?;
+    match element.attribute("full-path") {
+        Some(path) => Ok(path.to_owned()),
+        None => Err(missing("full-path attribute in container")),
+    }
 }
 
 fn parse_metadata<R: Read + Seek>(
     archive: &mut ZipArchive<R>,
-    metadata_path: &Path,
-) -> result::ZipResult<xml::XmlDocument> {
-    let mut metadata = archive.by_name(metadata_path.to_str().unwrap())?;
+    metadata_path: &str,
+) -> Result<xml::XmlDocument, Error> {
+    let mut metadata = archive.by_name(metadata_path)?;
 
-    if metadata.enclosed_name() == None {
-        panic!("failed to read epub data");
+    if metadata.enclosed_name().is_none() {
+        return Err(ZipError::InvalidArchive("invalid package document path").into());
     }
 
     let mut contents = String::new();
     metadata.read_to_string(&mut contents)?;
-    match xml::parse(&contents) {
-        Ok(document) => Ok(document),
-        // TODO: Add tests for this case
-        Err(error) => panic!("a problem occurred while parsing the book: {}", error),
+    Ok(xml::parse(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{write_epub, write_epub_with_files, write_epub_with_opf};
+
+    #[test]
+    fn parse_metadata() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub(&path, "urn:uuid:1234", "A Title", "An Author");
+
+        let ebook = parse(&path).unwrap();
+        assert_eq!(ebook.identifier, "urn:uuid:1234");
+        assert_eq!(ebook.title, "A Title");
+        assert_eq!(ebook.author(), "An Author");
+        assert_eq!(ebook.size, path.metadata().unwrap().len());
+    }
+
+    #[test]
+    fn parse_full_metadata() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_opf(
+            &path,
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier id="uid" opf:scheme="uuid">urn:uuid:1234</dc:identifier>
+    <dc:identifier>urn:isbn:9780141439518</dc:identifier>
+    <dc:title>Pride and Prejudice</dc:title>
+    <dc:creator opf:role="aut" opf:file-as="Austen, Jane">Jane Austen</dc:creator>
+    <dc:contributor opf:role="ill">Hugh Thomson</dc:contributor>
+    <dc:language>en</dc:language>
+    <dc:publisher>Penguin</dc:publisher>
+    <dc:date opf:event="modification">2020-01-01</dc:date>
+    <dc:date opf:event="publication">1813-01-28</dc:date>
+    <dc:description>A novel of manners.</dc:description>
+    <dc:subject>Fiction</dc:subject>
+    <dc:subject>Romance</dc:subject>
+    <dc:rights>Public domain</dc:rights>
+  </metadata>
+</package>"#,
+        );
+
+        let ebook = parse(&path).unwrap();
+        assert_eq!(ebook.identifier(), "urn:uuid:1234");
+        assert_eq!(
+            ebook.creators(),
+            [Contributor::new(
+                "Jane Austen".to_owned(),
+                Some("aut".to_owned()),
+                Some("Austen, Jane".to_owned())
+            )]
+        );
+        assert_eq!(ebook.contributors()[0].name(), "Hugh Thomson");
+        assert_eq!(ebook.contributors()[0].role(), Some("ill"));
+        assert_eq!(ebook.identifiers().len(), 2);
+        assert_eq!(ebook.identifiers()[0].scheme(), Some("UUID"));
+        assert_eq!(ebook.isbn(), Some("9780141439518"));
+        assert_eq!(ebook.languages(), ["en"]);
+        assert_eq!(ebook.publisher(), Some("Penguin"));
+        assert_eq!(ebook.date(), Some("1813-01-28"));
+        assert_eq!(ebook.year(), Some("1813"));
+        assert_eq!(ebook.description(), Some("A novel of manners."));
+        assert_eq!(ebook.subjects(), ["Fiction", "Romance"]);
+        assert_eq!(ebook.rights(), Some("Public domain"));
+        assert_eq!(ebook.version(), "2.0");
+    }
+
+    #[test]
+    fn parse_isbn_prefixes() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        for (identifier, isbn) in [
+            ("urn:isbn:9780141439518", "9780141439518"),
+            ("URN:ISBN:9780141439518", "9780141439518"),
+            ("isbn:9780141439518", "9780141439518"),
+            ("ISBN:978-0-14-143951-8", "978-0-14-143951-8"),
+        ] {
+            write_epub_with_opf(
+                &path,
+                &format!(
+                    r#"<package version="2.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">{}</dc:identifier>
+    <dc:title>Pride and Prejudice</dc:title>
+    <dc:creator>Jane Austen</dc:creator>
+  </metadata>
+</package>"#,
+                    identifier
+                ),
+            );
+            assert_eq!(parse(&path).unwrap().isbn(), Some(isbn));
+        }
+    }
+
+    #[test]
+    fn parse_epub3_refinements() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_opf(
+            &path,
+            r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:1234</dc:identifier>
+    <dc:identifier id="isbn">9780552152679</dc:identifier>
+    <meta refines="#isbn" property="identifier-type" scheme="onix:codelist5">15</meta>
+    <dc:title id="subtitle">A Discworld Novel</dc:title>
+    <meta refines="#subtitle" property="title-type">subtitle</meta>
+    <dc:title id="title">Guards! Guards!</dc:title>
+    <meta refines="#title" property="title-type">main</meta>
+    <meta refines="#title" property="file-as">Guards! Guards!</meta>
+    <dc:creator id="creator">Terry Pratchett</dc:creator>
+    <meta refines="#creator" property="role" scheme="marc:relators">aut</meta>
+    <meta refines="#creator" property="file-as">Pratchett, Terry</meta>
+    <meta property="belongs-to-collection" id="set">Favourites</meta>
+    <meta refines="#set" property="collection-type">set</meta>
+    <meta property="belongs-to-collection" id="series">Discworld</meta>
+    <meta refines="#series" property="collection-type">series</meta>
+    <meta refines="#series" property="group-position">8</meta>
+  </metadata>
+</package>"##,
+        );
+
+        let ebook = parse(&path).unwrap();
+        assert_eq!(ebook.title(), "Guards! Guards!");
+        assert_eq!(ebook.subtitle(), Some("A Discworld Novel"));
+        assert_eq!(ebook.creators()[0].role(), Some("aut"));
+        assert_eq!(ebook.author_sort(), "Pratchett, Terry");
+        assert_eq!(ebook.isbn(), Some("9780552152679"));
+        let series = ebook.series().unwrap();
+        assert_eq!(series.name(), "Discworld");
+        assert_eq!(series.index(), Some(8.0));
+    }
+
+    #[test]
+    fn parse_calibre_series() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_opf(
+            &path,
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier id="uid">1234</dc:identifier>
+    <dc:title>The Colour of Magic</dc:title>
+    <dc:creator>Terry Pratchett</dc:creator>
+    <meta name="calibre:series" content="Discworld"/>
+    <meta name="calibre:series_index" content="1.0"/>
+    <meta name="calibre:title_sort" content="Colour of Magic, The"/>
+  </metadata>
+</package>"#,
+        );
+
+        let ebook = parse(&path).unwrap();
+        let series = ebook.series().unwrap();
+        assert_eq!(series.name(), "Discworld");
+        assert_eq!(series.index(), Some(1.0));
+        assert_eq!(ebook.title_sort(), "Colour of Magic, The");
+        assert_eq!(ebook.author_sort(), "Terry Pratchett");
+    }
+
+    #[test]
+    fn parse_custom_namespace_prefix() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_opf(
+            &path,
+            r#"<opf:package xmlns:opf="http://www.idpf.org/2007/opf" version="2.0"
+             unique-identifier="uid">
+  <opf:metadata xmlns:purl="http://purl.org/dc/elements/1.1/">
+    <purl:identifier id="uid">1234</purl:identifier>
+    <purl:title>Frankenstein</purl:title>
+    <purl:creator>Mary Shelley</purl:creator>
+    <purl:description><![CDATA[<p>A <em>modern</em> Prometheus.</p>]]></purl:description>
+  </opf:metadata>
+</opf:package>"#,
+        );
+
+        let ebook = parse(&path).unwrap();
+        assert_eq!(ebook.title(), "Frankenstein");
+        assert_eq!(ebook.author(), "Mary Shelley");
+        assert_eq!(
+            ebook.description(),
+            Some("<p>A <em>modern</em> Prometheus.</p>")
+        );
+    }
+
+    #[test]
+    fn parse_malformed_metadata() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_opf(&path, "<package><metadata></package>");
+
+        assert!(matches!(parse(&path), Err(Error::Xml(_))));
+    }
+
+    /// Returns the contents of a package document with the given manifest and guide.
+    fn cover_opf(metadata: &str, manifest: &str, guide: &str) -> String {
+        format!(
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">1234</dc:identifier>
+    <dc:title>A Title</dc:title>
+    <dc:creator>An Author</dc:creator>
+    {}
+  </metadata>
+  <manifest>{}</manifest>
+  <guide>{}</guide>
+</package>"#,
+            metadata, manifest, guide
+        )
+    }
+
+    #[test]
+    fn cover_from_manifest_property() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        let opf = cover_opf(
+            "",
+            r#"<item id="c" href="images/My%20Cover.png" media-type="image/png"
+                 properties="cover-image"/>"#,
+            "",
+        );
+        write_epub_with_files(&path, &opf, &[("OEBPS/images/My Cover.png", b"png")]);
+
+        let cover = cover(&path).unwrap().unwrap();
+        assert_eq!(cover.extension, "png");
+        assert_eq!(cover.data, b"png");
+    }
+
+    #[test]
+    fn cover_from_meta_element() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        let opf = cover_opf(
+            r#"<meta name="cover" content="cover-id"/>"#,
+            r#"<item id="cover-id" href="../cover.jpeg" media-type="image/jpeg"/>"#,
+            "",
+        );
+        write_epub_with_files(&path, &opf, &[("cover.jpeg", b"jpeg")]);
+
+        let cover = cover(&path).unwrap().unwrap();
+        assert_eq!(cover.extension, "jpg");
+        assert_eq!(cover.data, b"jpeg");
+    }
+
+    #[test]
+    fn cover_from_guide_page() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        let opf = cover_opf(
+            "",
+            r#"<item id="page" href="text/cover.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<reference type="cover" href="text/cover.xhtml#top"/>"#,
+        );
+        let page = br#"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+<div><img src="../images/cover.gif" alt="Cover"/></div>
+</body></html>"#;
+        write_epub_with_files(
+            &path,
+            &opf,
+            &[
+                ("OEBPS/text/cover.xhtml", page),
+                ("OEBPS/images/cover.gif", b"gif"),
+            ],
+        );
+
+        let cover = cover(&path).unwrap().unwrap();
+        assert_eq!(cover.extension, "gif");
+        assert_eq!(cover.data, b"gif");
+    }
+
+    #[test]
+    fn cover_missing() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub(&path, "1234", "A Title", "An Author");
+
+        assert!(cover(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_missing_unique_identifier() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        write_epub_with_opf(
+            &path,
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>id</dc:identifier>
+    <dc:title>A Title</dc:title>
+  </metadata>
+</package>"#,
+        );
+
+        match parse(&path) {
+            Err(Error::MissingMetadata(field)) => assert_eq!(field, "unique identifier attribute"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parse_not_an_archive() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("book.epub");
+        std::fs::write(&path, "not a zip file").unwrap();
+
+        assert!(matches!(parse(&path), Err(Error::Zip(_))));
     }
 }
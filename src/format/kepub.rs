@@ -0,0 +1,273 @@
+//! Conversion of EPUBs to Kobo's KEPUB format.
+//!
+//! A KEPUB is an EPUB whose content documents wrap every sentence in a `koboSpan` element with an
+//! id of the form `kobo.<paragraph>.<sentence>`. Kobo eReaders use the spans to track reading
+//! statistics and to paginate the book.
+
+use std::fs::File;
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+use xml::writer::{self, EmitterConfig};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::Error;
+
+/// Elements starting a new paragraph. Sentences are numbered from one in each paragraph.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "div",
+    "blockquote",
+    "pre",
+    "td",
+    "th",
+    "dt",
+    "dd",
+    "figcaption",
+];
+
+/// Elements whose text is not shown to the reader and must be left alone.
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Returns true if the archive entry at `name` is an XHTML content document.
+fn is_content_document(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".xhtml") || name.ends_with(".html") || name.ends_with(".htm")
+}
+
+/// Splits text into sentences. Each sentence keeps its closing punctuation, quotes and the
+/// whitespace following it, so the sentences joined together give back the original text.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        while let Some(&(_, c)) = chars.peek() {
+            if matches!(c, '.' | '!' | '?' | '"' | '\'' | '”' | '’' | ')') {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let mut end = None;
+        while let Some(&(i, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                end = Some(i + c.len_utf8());
+            } else {
+                break;
+            }
+        }
+        // Only break after whitespace, so numbers like "3.5" stay in one sentence.
+        if let Some(end) = end {
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+/// Adds Kobo spans to the sentences in the body of an XHTML content document.
+fn add_spans(document: &[u8]) -> Result<Vec<u8>, Error> {
+    let config = ParserConfig::new()
+        .cdata_to_characters(true)
+        .ignore_comments(false);
+    let reader = EventReader::new_with_config(document, config);
+    let mut output = Vec::new();
+    let mut writer = EmitterConfig::new()
+        .perform_indent(false)
+        .create_writer(&mut output);
+    let write_error = |e: writer::Error| Error::Io(io::Error::other(e));
+
+    let mut in_body = false;
+    // The number of skipped elements the reader is currently inside of.
+    let mut skipped = 0;
+    let mut paragraph = 0;
+    let mut sentence = 0;
+    let mut new_paragraph = true;
+    for event in reader {
+        let event = event?;
+        match &event {
+            XmlEvent::StartElement { name, .. } => {
+                let tag = name.local_name.as_str();
+                if tag == "body" {
+                    in_body = true;
+                } else if SKIPPED_ELEMENTS.contains(&tag) {
+                    skipped += 1;
+                } else if BLOCK_ELEMENTS.contains(&tag) {
+                    new_paragraph = true;
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                let tag = name.local_name.as_str();
+                if tag == "body" {
+                    in_body = false;
+                } else if SKIPPED_ELEMENTS.contains(&tag) {
+                    skipped -= 1;
+                } else if BLOCK_ELEMENTS.contains(&tag) {
+                    new_paragraph = true;
+                }
+            }
+            XmlEvent::Characters(text) if in_body && skipped == 0 => {
+                if new_paragraph {
+                    paragraph += 1;
+                    sentence = 0;
+                    new_paragraph = false;
+                }
+                for text in sentences(text) {
+                    sentence += 1;
+                    let id = format!("kobo.{}.{}", paragraph, sentence);
+                    writer
+                        .write(
+                            writer::XmlEvent::start_element("span")
+                                .attr("class", "koboSpan")
+                                .attr("id", &id),
+                        )
+                        .map_err(write_error)?;
+                    writer
+                        .write(writer::XmlEvent::characters(text))
+                        .map_err(write_error)?;
+                    writer
+                        .write(writer::XmlEvent::end_element())
+                        .map_err(write_error)?;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(write_error)?;
+        }
+    }
+    Ok(output)
+}
+
+/// Converts the EPUB at `source` to a KEPUB written to `destination`. Content documents which
+/// cannot be parsed are copied unchanged.
+pub fn convert(source: &Path, destination: &Path) -> Result<(), Error> {
+    let mut archive = ZipArchive::new(File::open(source)?)?;
+    let mut zip = ZipWriter::new(File::create(destination)?);
+
+    // The mimetype must come first and be stored uncompressed for the book to be recognized.
+    if let Ok(mut mimetype) = archive.by_name("mimetype") {
+        let mut contents = Vec::new();
+        mimetype.read_to_end(&mut contents)?;
+        zip.start_file(
+            "mimetype",
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(&contents)?;
+    }
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_owned();
+        if name == "mimetype" {
+            continue;
+        }
+        if !is_content_document(&name) {
+            zip.raw_copy_file(file)?;
+            continue;
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let contents = add_spans(&contents).unwrap_or(contents);
+        zip.start_file(name, FileOptions::default())?;
+        zip.write_all(&contents)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::write_epub_with_files;
+
+    #[test]
+    fn split_sentences() {
+        assert_eq!(
+            sentences("It was 3.5 miles. \"Far!\" he said. Was it?"),
+            ["It was 3.5 miles. ", "\"Far!\" ", "he said. ", "Was it?"]
+        );
+        assert_eq!(sentences("No end"), ["No end"]);
+    }
+
+    #[test]
+    fn add_spans_to_body() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Emma</title><style>p { margin: 0 }</style></head>
+<body><h1>Chapter I</h1><p>Emma was handsome. She was <em>clever</em>.</p></body></html>"#;
+
+        let output = String::from_utf8(add_spans(document.as_bytes()).unwrap()).unwrap();
+        assert!(output.contains("<title>Emma</title>"));
+        assert!(output.contains("<style>p { margin: 0 }</style>"));
+        assert!(
+            output.contains(r#"<h1><span class="koboSpan" id="kobo.1.1">Chapter I</span></h1>"#)
+        );
+        assert!(output.contains(
+            r#"<p><span class="koboSpan" id="kobo.2.1">Emma was handsome. </span><span class="koboSpan" id="kobo.2.2">She was </span><em><span class="koboSpan" id="kobo.2.3">clever</span></em><span class="koboSpan" id="kobo.2.4">.</span></p>"#
+        ));
+    }
+
+    #[test]
+    fn convert_epub() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let source = dir.path().join("book.epub");
+        let destination = dir.path().join("book.kepub.epub");
+        let chapter =
+            r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><p>Hello.</p></body></html>"#;
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"
+         unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">urn:uuid:1</dc:identifier>
+    <dc:title>Hello</dc:title>
+    <dc:creator>An Author</dc:creator>
+  </metadata>
+</package>"#;
+        write_epub_with_files(
+            &source,
+            opf,
+            &[
+                ("OEBPS/chapter.xhtml", chapter.as_bytes()),
+                ("OEBPS/image.jpg", b"jpeg data"),
+            ],
+        );
+
+        convert(&source, &destination).unwrap();
+        let mut archive = ZipArchive::new(File::open(&destination).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        let mut chapter = String::new();
+        archive
+            .by_name("OEBPS/chapter.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert!(chapter.contains(r#"<span class="koboSpan" id="kobo.1.1">Hello.</span>"#));
+        let mut image = Vec::new();
+        archive
+            .by_name("OEBPS/image.jpg")
+            .unwrap()
+            .read_to_end(&mut image)
+            .unwrap();
+        assert_eq!(image, b"jpeg data");
+        assert_eq!(
+            crate::format::epub::parse(&destination).unwrap().title(),
+            "Hello"
+        );
+    }
+}
@@ -1,18 +1,23 @@
 use std::error::Error;
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::{Duration, Instant};
 
+use chrono::Utc;
+
+use crate::catalog::Catalog;
 use crate::common;
 use crate::config;
+use crate::cover;
 use crate::format::epub;
-use crate::list;
 
 struct ImportStats {
     imported: u32,
     skipped: u32,
+    /// The books which failed to import, along with the reason.
+    failures: Vec<(PathBuf, Box<dyn Error>)>,
     elapsed: Duration,
 }
 
@@ -20,72 +25,75 @@ impl fmt::Display for ImportStats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "imported {}; skipped {}; finished in {:.2}s",
+            "imported {}; skipped {}; failed {}; finished in {:.2}s",
             self.imported,
             self.skipped,
+            self.failures.len(),
             self.elapsed.as_secs_f32()
         )
     }
 }
 
+/// The result of importing a single file.
+enum Outcome {
+    Imported,
+    Skipped,
+}
+
 pub fn run(
     config: &config::Config,
     path: &Path,
     move_books: bool,
     dry_run: bool,
+    fail_fast: bool,
 ) -> Result<(), Box<dyn Error>> {
     if !path.is_dir() {
         return Err(format!("invalid path: '{}'", path.display()).into());
     }
+    import_paths(
+        config,
+        common::find_ebooks(path)?,
+        move_books,
+        dry_run,
+        fail_fast,
+    )
+}
 
+/// Imports each of the ebooks at `ebook_paths` into the library and prints a summary.
+pub(crate) fn import_paths(
+    config: &config::Config,
+    ebook_paths: Vec<PathBuf>,
+    move_books: bool,
+    dry_run: bool,
+    fail_fast: bool,
+) -> Result<(), Box<dyn Error>> {
     let mut stats = ImportStats {
         imported: 0,
         skipped: 0,
+        failures: Vec::new(),
         elapsed: Duration::ZERO,
     };
     let start = Instant::now();
-    let ebooks = list::get_ebooks(&config.library)?;
-    let ebook_paths = common::find_ebooks(&path)?;
+    let mut catalog = Catalog::open(&config.library)?;
     for path in ebook_paths {
-        let ebook = match epub::parse(path.as_path()) {
-            Ok(ebook) => ebook,
-            Err(e) => return Err(e),
-        };
-
-        if ebooks
-            .iter()
-            .find(|e| e.identifier == ebook.identifier)
-            .is_some()
-        {
-            println!("skipping \"{}\" -- previously imported", ebook.title);
-            stats.skipped += 1;
-            continue;
-        }
-
-        let author = common::sanitize(&ebook.author);
-        let title = common::sanitize(&ebook.title);
-
-        let mut destination = config.library.clone();
-        destination.push(format!("{}/{}", author, title));
-        if !dry_run {
-            fs::create_dir_all(&destination)?;
-        }
-        destination.push(format!("{}.epub", title));
-        if !dry_run {
-            if move_books {
-                common::move_file(&ebook.path, &destination)?;
-            } else {
-                common::copy(&ebook.path, &destination)?;
-            }
-            // Update the last modified timestamp of the book so the import date shows up when
-            // running the list command.
-            if cfg!(target_family = "unix") {
-                Command::new("touch").arg(&destination).status()?;
+        match import_file(config, &mut catalog, &path, move_books, dry_run) {
+            Ok(Outcome::Imported) => stats.imported += 1,
+            Ok(Outcome::Skipped) => stats.skipped += 1,
+            Err(e) => {
+                if fail_fast {
+                    // Record the books imported so far before bailing out.
+                    if !dry_run {
+                        catalog.save()?;
+                    }
+                    return Err(format!("failed to import '{}': {}", path.display(), e).into());
+                }
+                println!("failed to import '{}' -- {}", path.display(), e);
+                stats.failures.push((path, e));
             }
-            // TODO: Support other platforms
         }
-        stats.imported += 1;
-        println!("imported \"{}\"", ebook.title);
+    }
+    if !dry_run {
+        catalog.save()?;
     }
     stats.elapsed = start.elapsed();
     print!("\n{}", stats);
@@ -93,5 +101,68 @@ pub fn run(
         print!("; dry run");
     }
     println!();
+
+    if !stats.failures.is_empty() {
+        println!("\nthe following books could not be imported:");
+        for (path, e) in &stats.failures {
+            println!("  {}: {}", path.display(), e);
+        }
+        return Err(format!(
+            "failed to import {} of the selected books",
+            stats.failures.len()
+        )
+        .into());
+    }
     Ok(())
 }
+
+/// Imports the ebook at `path` into the library, adding it to the catalog.
+fn import_file(
+    config: &config::Config,
+    catalog: &mut Catalog,
+    path: &Path,
+    move_books: bool,
+    dry_run: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let mut ebook = epub::parse(path)?;
+
+    if catalog.find(&ebook.identifier).is_some() {
+        println!("skipping \"{}\" -- previously imported", ebook.title);
+        return Ok(Outcome::Skipped);
+    }
+
+    let destination = config.library.join(config.path_template.render(&ebook)?);
+    if !dry_run {
+        let dir = destination.parent().unwrap();
+        fs::create_dir_all(dir)?;
+        let result = if move_books {
+            common::move_file(&ebook.path, &destination)
+        } else {
+            common::copy(&ebook.path, &destination)
+        };
+        if let Err(e) = result {
+            // Don't leave the directories created for the book behind.
+            let _ = common::remove_empty_dirs(dir, &config.library);
+            return Err(e.into());
+        }
+        // Update the last modified timestamp of the book so the import date shows up when
+        // running the list command.
+        if cfg!(target_family = "unix") {
+            Command::new("touch").arg(&destination).status()?;
+        }
+        // TODO: Support other platforms
+        ebook.set_file_info(&destination.metadata()?)?;
+        // The cover is only a convenience, so a broken one shouldn't fail the import.
+        if let Err(e) = cover::save(&destination) {
+            eprintln!(
+                "warning: failed to save the cover of \"{}\" -- {}",
+                ebook.title, e
+            );
+        }
+    }
+    println!("imported \"{}\"", ebook.title);
+    ebook.path = destination;
+    ebook.imported = Utc::now();
+    catalog.insert(ebook);
+    Ok(Outcome::Imported)
+}
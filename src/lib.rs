@@ -3,33 +3,299 @@
 //! eBook management tool.
 
 mod common;
+mod error;
 mod format;
 mod xml;
 
+#[cfg(test)]
+mod fixtures;
+
+pub mod catalog;
 pub mod config;
+pub mod cover;
 pub mod device;
 pub mod import;
 pub mod list;
+pub mod output;
+pub mod query;
+pub mod reorganize;
+pub mod template;
 pub mod upload;
 
+use std::fs::Metadata;
+use std::io;
 use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub use error::Error;
+
 /// Represents an eBook.
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Ebook {
     identifier: String,
     title: String,
-    author: String,
+    subtitle: Option<String>,
+    title_sort: Option<String>,
+    creators: Vec<Contributor>,
+    contributors: Vec<Contributor>,
+    identifiers: Vec<Identifier>,
+    languages: Vec<String>,
+    publisher: Option<String>,
+    date: Option<String>,
+    description: Option<String>,
+    subjects: Vec<String>,
+    rights: Option<String>,
+    series: Option<Series>,
+    version: String,
     path: PathBuf,
+    size: u64,
+    modified: i64,
+    imported: DateTime<Utc>,
+}
+
+/// A person or organization responsible for creating or contributing to an eBook.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Contributor {
+    name: String,
+    role: Option<String>,
+    file_as: Option<String>,
+}
+
+/// A series of eBooks, along with the position of an eBook within it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Series {
+    name: String,
+    index: Option<f64>,
+}
+
+/// An identifier of an eBook, such as an ISBN or UUID.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Identifier {
+    scheme: Option<String>,
+    value: String,
 }
 
 impl Ebook {
-    fn new(identifier: String, title: String, author: String, path: &Path) -> Ebook {
+    fn new(identifier: String, title: String, creators: Vec<Contributor>, path: &Path) -> Ebook {
         Ebook {
             identifier,
             title,
-            author,
+            subtitle: None,
+            title_sort: None,
+            creators,
+            contributors: Vec::new(),
+            identifiers: Vec::new(),
+            languages: Vec::new(),
+            publisher: None,
+            date: None,
+            description: None,
+            subjects: Vec::new(),
+            rights: None,
+            series: None,
+            version: String::new(),
             path: path.to_path_buf(),
+            size: 0,
+            modified: 0,
+            imported: DateTime::UNIX_EPOCH,
         }
     }
+
+    /// Records the size and last modified time (in seconds since the Unix epoch) of the file
+    /// backing the ebook. The import date defaults to the last modified time until the catalog
+    /// records the actual date the book was imported.
+    fn set_file_info(&mut self, metadata: &Metadata) -> io::Result<()> {
+        let (size, modified) = common::file_info(metadata)?;
+        self.size = size;
+        self.modified = modified;
+        self.imported = DateTime::from_timestamp(modified, 0).unwrap_or_default();
+        Ok(())
+    }
+
+    /// Returns the unique identifier of the eBook.
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// Returns the main title of the eBook.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Returns the subtitle of the eBook.
+    pub fn subtitle(&self) -> Option<&str> {
+        self.subtitle.as_deref()
+    }
+
+    /// Returns the title of the eBook in a form suitable for sorting, falling back to the title.
+    pub fn title_sort(&self) -> &str {
+        self.title_sort.as_deref().unwrap_or(&self.title)
+    }
+
+    /// Returns the name of the primary author, or an empty string if the eBook has no creators.
+    pub fn author(&self) -> &str {
+        self.creators.first().map_or("", |creator| &creator.name)
+    }
+
+    /// Returns the name of the primary author in a form suitable for sorting, falling back to
+    /// their display name.
+    pub fn author_sort(&self) -> &str {
+        self.creators.first().map_or("", |creator| {
+            creator.file_as.as_deref().unwrap_or(&creator.name)
+        })
+    }
+
+    /// Returns the primary creators (usually the authors) of the eBook.
+    pub fn creators(&self) -> &[Contributor] {
+        &self.creators
+    }
+
+    /// Returns the secondary contributors of the eBook, such as editors and illustrators.
+    pub fn contributors(&self) -> &[Contributor] {
+        &self.contributors
+    }
+
+    /// Returns every identifier of the eBook, including the unique identifier.
+    pub fn identifiers(&self) -> &[Identifier] {
+        &self.identifiers
+    }
+
+    /// Returns the ISBN of the eBook, if it has one. Any `urn:isbn:` or `isbn:` prefix is removed.
+    pub fn isbn(&self) -> Option<&str> {
+        let isbn = self
+            .identifiers
+            .iter()
+            .find(|identifier| identifier.scheme.as_deref() == Some("ISBN"))?;
+        let value = isbn.value.as_str();
+        let prefix = ["urn:isbn:", "isbn:"].into_iter().find(|prefix| {
+            value
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        });
+        Some(prefix.map_or(value, |prefix| &value[prefix.len()..]))
+    }
+
+    /// Returns the languages of the content of the eBook, as language tags such as `en-US`.
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    /// Returns the publisher of the eBook.
+    pub fn publisher(&self) -> Option<&str> {
+        self.publisher.as_deref()
+    }
+
+    /// Returns the publication date of the eBook, as written in its metadata.
+    pub fn date(&self) -> Option<&str> {
+        self.date.as_deref()
+    }
+
+    /// Returns the year the eBook was published, if the publication date starts with a year.
+    pub fn year(&self) -> Option<&str> {
+        self.date
+            .as_deref()
+            .and_then(|date| date.get(..4))
+            .filter(|year| year.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    /// Returns the description of the eBook.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Returns the subjects (keywords or categories) of the eBook.
+    pub fn subjects(&self) -> &[String] {
+        &self.subjects
+    }
+
+    /// Returns the rights statement of the eBook.
+    pub fn rights(&self) -> Option<&str> {
+        self.rights.as_deref()
+    }
+
+    /// Returns the series the eBook belongs to.
+    pub fn series(&self) -> Option<&Series> {
+        self.series.as_ref()
+    }
+
+    /// Returns the EPUB version of the eBook, such as `2.0` or `3.0`.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Returns the path of the eBook file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the size of the eBook file, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the date the eBook was imported into the library.
+    pub fn imported(&self) -> DateTime<Utc> {
+        self.imported
+    }
+}
+
+impl Contributor {
+    fn new(name: String, role: Option<String>, file_as: Option<String>) -> Contributor {
+        Contributor {
+            name,
+            role,
+            file_as,
+        }
+    }
+
+    /// Returns the display name of the contributor.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the MARC relator code describing the role of the contributor, such as `aut` for
+    /// author or `ill` for illustrator.
+    pub fn role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+
+    /// Returns the name of the contributor in a form suitable for sorting, such as "Dickens,
+    /// Charles".
+    pub fn file_as(&self) -> Option<&str> {
+        self.file_as.as_deref()
+    }
+}
+
+impl Series {
+    fn new(name: String, index: Option<f64>) -> Series {
+        Series { name, index }
+    }
+
+    /// Returns the name of the series.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the position of the eBook within the series. Positions may be fractional, such as
+    /// `2.5` for a novella set between the second and third books.
+    pub fn index(&self) -> Option<f64> {
+        self.index
+    }
+}
+
+impl Identifier {
+    fn new(scheme: Option<String>, value: String) -> Identifier {
+        Identifier { scheme, value }
+    }
+
+    /// Returns the scheme of the identifier, such as `ISBN`, `UUID` or `DOI`.
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    /// Returns the value of the identifier.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
@@ -1,97 +1,506 @@
-use std::cmp;
+use std::cmp::{self, Ordering};
 use std::error::Error;
-use std::fs::Metadata;
 use std::io::{self, Write};
 use std::path::Path;
+use std::str::FromStr;
 
-#[cfg(target_family = "unix")]
-use std::os::unix::fs::MetadataExt;
-
-use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::Serialize;
 use tabwriter::TabWriter;
 
+use super::catalog::Catalog;
 use super::common;
 use super::config;
-use super::format::epub;
-use super::Ebook;
+use super::output::{self, Format};
+use super::query::Query;
+use super::{Contributor, Ebook, Identifier};
 
-/// Returns a vector of ebooks read from files in the given directory.
+/// Returns a vector of ebooks stored in the library at the given directory.
 pub fn get_ebooks(path: &Path) -> Result<Vec<Ebook>, Box<dyn Error>> {
-    let mut ebooks: Vec<Ebook> = Vec::new();
-    let ebook_paths = common::find_ebooks(&path)?;
-
-    for path in ebook_paths {
-        let ebook = match epub::parse(path.as_path()) {
-            Ok(ebook) => ebook,
-            Err(e) => return Err(e),
-        };
-        ebooks.push(ebook);
-    }
-    Ok(ebooks)
-}
-
-#[cfg(target_family = "unix")]
-fn modified_datetime(metadata: &Metadata) -> DateTime<Utc> {
-    let naive = NaiveDateTime::from_timestamp(metadata.mtime(), 0);
-    DateTime::from_utc(naive, Utc)
-}
-
-// TODO: Support the more accurate "last modified" datetime on other platforms (this will require
-// support for updating the last modified timestamp when importing books on other platforms as
-// well).
-#[cfg(not(target_family = "unix"))]
-fn modified_datetime(metadata: &Metadata) -> DateTime<Utc> {
-    DateTime::from(metadata.created().unwrap_or_default(0))
+    Ok(Catalog::open(path)?.into_ebooks())
 }
 
 /// Returns a string representation of the date the ebook was added to the library. The returned
 /// string is suitable for display to the user.
 fn created(ebook: &Ebook) -> String {
-    let metadata = ebook.path.metadata().expect("failed to read file metadata");
     format!(
         "{}",
-        modified_datetime(&metadata)
-            .with_timezone(&Local)
-            .format("%B %d, %Y")
+        ebook.imported.with_timezone(&Local).format("%B %d, %Y")
     )
 }
 
-pub fn run(config: &config::Config) -> Result<(), Box<dyn Error>> {
-    let ebooks = get_ebooks(&config.library)?;
+/// The order books are listed in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Title,
+    Author,
+    Created,
+    Series,
+    Size,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s {
+            "title" => Ok(SortKey::Title),
+            "author" => Ok(SortKey::Author),
+            "created" => Ok(SortKey::Created),
+            "series" => Ok(SortKey::Series),
+            "size" => Ok(SortKey::Size),
+            _ => Err(format!(
+                "unknown sort key '{}' (expected one of title, author, created, series, size)",
+                s
+            )),
+        }
+    }
+}
+
+/// A metadata field which can be shown as a column of the book listing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Title,
+    Author,
+    Created,
+    Series,
+    Size,
+    Identifier,
+    Language,
+    Publisher,
+    Date,
+    Isbn,
+    Path,
+}
+
+/// The names of every column, in the order they are accepted by `--columns`.
+const COLUMNS: &[(&str, Column)] = &[
+    ("title", Column::Title),
+    ("author", Column::Author),
+    ("created", Column::Created),
+    ("series", Column::Series),
+    ("size", Column::Size),
+    ("identifier", Column::Identifier),
+    ("language", Column::Language),
+    ("publisher", Column::Publisher),
+    ("date", Column::Date),
+    ("isbn", Column::Isbn),
+    ("path", Column::Path),
+];
+
+impl Column {
+    /// Returns the heading of the column.
+    fn heading(&self) -> &'static str {
+        match self {
+            Column::Title => "Title",
+            Column::Author => "Author",
+            Column::Created => "Created",
+            Column::Series => "Series",
+            Column::Size => "Size",
+            Column::Identifier => "Identifier",
+            Column::Language => "Language",
+            Column::Publisher => "Publisher",
+            Column::Date => "Date",
+            Column::Isbn => "ISBN",
+            Column::Path => "Path",
+        }
+    }
+
+    /// Returns the value of the column for the given ebook, formatted for display.
+    fn value(&self, ebook: &Ebook) -> String {
+        match self {
+            Column::Title => ebook.title.clone(),
+            Column::Author => ebook.author().to_owned(),
+            Column::Created => created(ebook),
+            Column::Series => match &ebook.series {
+                Some(series) => match series.index {
+                    Some(index) => format!("{} #{}", series.name, common::format_number(index)),
+                    None => series.name.clone(),
+                },
+                None => String::new(),
+            },
+            Column::Size => common::format_size(ebook.size),
+            Column::Identifier => ebook.identifier.clone(),
+            Column::Language => ebook.languages.join(", "),
+            Column::Publisher => ebook.publisher.clone().unwrap_or_default(),
+            Column::Date => ebook.date.clone().unwrap_or_default(),
+            Column::Isbn => ebook.isbn().unwrap_or_default().to_owned(),
+            Column::Path => ebook.path.display().to_string(),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
 
-    // Calculate the maximum length of each category, in order to determine the correct number of
-    // "-" characters to add below the header for each column. This is pretty inefficient, but
-    // works for now.
-    let mut maxlen: Vec<usize> = vec![0, 0, 0];
-    for ebook in &ebooks {
-        maxlen[0] = cmp::max(maxlen[0], ebook.title.len());
-        maxlen[1] = cmp::max(maxlen[1], ebook.author.len());
-        maxlen[2] = cmp::max(maxlen[2], created(&ebook).len());
+    fn from_str(s: &str) -> Result<Column, String> {
+        COLUMNS
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, column)| *column)
+            .ok_or_else(|| {
+                let names: Vec<&str> = COLUMNS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown column '{}' (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Parses a comma-separated list of column names, such as `title,author,series`.
+pub fn parse_columns(s: &str) -> Result<Vec<Column>, String> {
+    s.split(',').map(|name| name.trim().parse()).collect()
+}
+
+/// Options controlling which books are listed and how they are shown.
+#[derive(Debug)]
+pub struct Options {
+    /// Only list books selected by this query.
+    pub query: Option<Query>,
+    pub sort: SortKey,
+    pub reverse: bool,
+    /// Only list books with an author whose name contains this text, ignoring case.
+    pub author: Option<String>,
+    /// Only list books whose title contains this text, ignoring case.
+    pub title: Option<String>,
+    /// Only list books imported on or after this date.
+    pub since: Option<NaiveDate>,
+    /// Only list books imported on or before this date.
+    pub until: Option<NaiveDate>,
+    /// The columns of the table. Other formats always include every field.
+    pub columns: Vec<Column>,
+    pub format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            query: None,
+            sort: SortKey::Created,
+            reverse: false,
+            author: None,
+            title: None,
+            since: None,
+            until: None,
+            columns: vec![Column::Title, Column::Author, Column::Created],
+            format: Format::Table,
+        }
+    }
+}
+
+impl Options {
+    /// Returns whether the ebook passes every filter.
+    fn matches(&self, ebook: &Ebook) -> bool {
+        let contains =
+            |value: &str, pattern: &str| value.to_lowercase().contains(&pattern.to_lowercase());
+        let imported = ebook.imported.with_timezone(&Local).date_naive();
+
+        self.query.as_ref().is_none_or(|query| query.matches(ebook))
+            && self.author.as_ref().is_none_or(|author| {
+                ebook
+                    .creators
+                    .iter()
+                    .any(|creator| contains(&creator.name, author))
+            })
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|title| contains(&ebook.title, title))
+            && self.since.is_none_or(|since| imported >= since)
+            && self.until.is_none_or(|until| imported <= until)
+    }
+}
+
+/// Compares two ebooks by the given sort key. Ties are broken by title.
+fn compare(a: &Ebook, b: &Ebook, key: SortKey) -> Ordering {
+    let title = |ebook: &Ebook| ebook.title_sort().to_lowercase();
+    let ordering = match key {
+        SortKey::Title => Ordering::Equal,
+        SortKey::Author => a
+            .author_sort()
+            .to_lowercase()
+            .cmp(&b.author_sort().to_lowercase()),
+        SortKey::Created => a.imported.cmp(&b.imported),
+        // Books which aren't part of a series are listed last.
+        SortKey::Series => match (&a.series, &b.series) {
+            (Some(a), Some(b)) => a
+                .name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then(a.index.partial_cmp(&b.index).unwrap_or(Ordering::Equal)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+        SortKey::Size => a.size.cmp(&b.size),
+    };
+    ordering.then_with(|| title(a).cmp(&title(b)))
+}
+
+/// Returns the ebooks passing the filters in `options`, in the requested order.
+fn select(ebooks: Vec<Ebook>, options: &Options) -> Vec<Ebook> {
+    let mut ebooks: Vec<Ebook> = ebooks
+        .into_iter()
+        .filter(|ebook| options.matches(ebook))
+        .collect();
+    ebooks.sort_by(|a, b| compare(a, b, options.sort));
+    if options.reverse {
+        ebooks.reverse();
+    }
+    ebooks
+}
+
+/// The representation of an ebook in machine-readable output. The field names are relied upon
+/// by scripts, so existing fields must not be renamed.
+#[derive(Serialize)]
+struct Record<'a> {
+    identifier: &'a str,
+    title: &'a str,
+    subtitle: Option<&'a str>,
+    title_sort: &'a str,
+    author: &'a str,
+    author_sort: &'a str,
+    creators: &'a [Contributor],
+    contributors: &'a [Contributor],
+    identifiers: &'a [Identifier],
+    isbn: Option<&'a str>,
+    languages: &'a [String],
+    publisher: Option<&'a str>,
+    date: Option<&'a str>,
+    description: Option<&'a str>,
+    subjects: &'a [String],
+    rights: Option<&'a str>,
+    series: Option<&'a str>,
+    series_index: Option<f64>,
+    version: &'a str,
+    path: &'a Path,
+    size: u64,
+    imported: DateTime<Utc>,
+}
+
+/// The names of the fields of a record, in the order they are printed as delimited values.
+const RECORD_FIELDS: &[&str] = &[
+    "identifier",
+    "title",
+    "subtitle",
+    "title_sort",
+    "author",
+    "author_sort",
+    "creators",
+    "contributors",
+    "identifiers",
+    "isbn",
+    "languages",
+    "publisher",
+    "date",
+    "description",
+    "subjects",
+    "rights",
+    "series",
+    "series_index",
+    "version",
+    "path",
+    "size",
+    "imported",
+];
+
+impl<'a> Record<'a> {
+    fn new(ebook: &'a Ebook) -> Record<'a> {
+        Record {
+            identifier: ebook.identifier(),
+            title: ebook.title(),
+            subtitle: ebook.subtitle(),
+            title_sort: ebook.title_sort(),
+            author: ebook.author(),
+            author_sort: ebook.author_sort(),
+            creators: ebook.creators(),
+            contributors: ebook.contributors(),
+            identifiers: ebook.identifiers(),
+            isbn: ebook.isbn(),
+            languages: ebook.languages(),
+            publisher: ebook.publisher(),
+            date: ebook.date(),
+            description: ebook.description(),
+            subjects: ebook.subjects(),
+            rights: ebook.rights(),
+            series: ebook.series().map(|series| series.name()),
+            series_index: ebook.series().and_then(|series| series.index()),
+            version: ebook.version(),
+            path: ebook.path(),
+            size: ebook.size(),
+            imported: ebook.imported(),
+        }
+    }
+
+    /// Returns the values of the record in the order of `RECORD_FIELDS`. Lists are joined with
+    /// semicolons.
+    fn values(&self) -> Vec<String> {
+        let names = |contributors: &[Contributor]| {
+            let names: Vec<&str> = contributors.iter().map(Contributor::name).collect();
+            names.join("; ")
+        };
+        let identifiers: Vec<&str> = self.identifiers.iter().map(Identifier::value).collect();
+        vec![
+            self.identifier.to_owned(),
+            self.title.to_owned(),
+            self.subtitle.unwrap_or_default().to_owned(),
+            self.title_sort.to_owned(),
+            self.author.to_owned(),
+            self.author_sort.to_owned(),
+            names(self.creators),
+            names(self.contributors),
+            identifiers.join("; "),
+            self.isbn.unwrap_or_default().to_owned(),
+            self.languages.join("; "),
+            self.publisher.unwrap_or_default().to_owned(),
+            self.date.unwrap_or_default().to_owned(),
+            self.description.unwrap_or_default().to_owned(),
+            self.subjects.join("; "),
+            self.rights.unwrap_or_default().to_owned(),
+            self.series.unwrap_or_default().to_owned(),
+            self.series_index
+                .map(common::format_number)
+                .unwrap_or_default(),
+            self.version.to_owned(),
+            self.path.display().to_string(),
+            self.size.to_string(),
+            self.imported.to_rfc3339(),
+        ]
+    }
+}
+
+pub fn run(config: &config::Config, options: &Options) -> Result<(), Box<dyn Error>> {
+    let ebooks = select(get_ebooks(&config.library)?, options);
+    match options.format {
+        Format::Table => print_table(&ebooks, &options.columns),
+        Format::Json => {
+            let records: Vec<Record> = ebooks.iter().map(Record::new).collect();
+            output::write_json(&records)
+        }
+        Format::Csv | Format::Tsv => {
+            let rows: Vec<Vec<String>> = ebooks
+                .iter()
+                .map(|ebook| Record::new(ebook).values())
+                .collect();
+            output::write_delimited(options.format, RECORD_FIELDS, &rows)
+        }
+    }
+}
+
+/// Prints the given columns of each ebook as a table.
+fn print_table(ebooks: &[Ebook], columns: &[Column]) -> Result<(), Box<dyn Error>> {
+    let rows: Vec<Vec<String>> = ebooks
+        .iter()
+        .map(|ebook| columns.iter().map(|column| column.value(ebook)).collect())
+        .collect();
+
+    // Calculate the maximum length of each column, in order to determine the correct number of
+    // "-" characters to add below the header for each column.
+    let mut maxlen: Vec<usize> = vec![0; columns.len()];
+    for row in &rows {
+        for (len, value) in maxlen.iter_mut().zip(row) {
+            *len = cmp::max(*len, value.chars().count());
+        }
     }
 
     let mut tw = TabWriter::new(io::stdout());
-    write!(&mut tw, "Title\tAuthor\tCreated\n").unwrap();
+    let headings: Vec<&str> = columns.iter().map(Column::heading).collect();
+    writeln!(&mut tw, "{}", headings.join("\t")).unwrap();
     // Note: the dash character here is an en dash, to make the separating line look even and not
     // have spaces in between each dash.
-    write!(
-        &mut tw,
-        "{}\t{}\t{}\n",
-        "–".repeat(maxlen[0]),
-        "–".repeat(maxlen[1]),
-        "–".repeat(maxlen[2])
-    )
-    .unwrap();
-    // TODO: Sort by date added
-    for ebook in ebooks {
-        write!(
-            &mut tw,
-            "{}\t{}\t{}\n",
-            ebook.title,
-            ebook.author,
-            created(&ebook)
-        )
-        .unwrap();
+    let separators: Vec<String> = maxlen.iter().map(|len| "–".repeat(*len)).collect();
+    writeln!(&mut tw, "{}", separators.join("\t")).unwrap();
+    for row in rows {
+        writeln!(&mut tw, "{}", row.join("\t")).unwrap();
     }
     tw.flush().unwrap();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::EbookBuilder;
+    use crate::Series;
+
+    fn ebook(title: &str, author: &str, imported: (i32, u32, u32)) -> Ebook {
+        EbookBuilder::new(title, author)
+            .identifier(title)
+            .imported(imported.0, imported.1, imported.2)
+            .build()
+    }
+
+    fn titles(ebooks: &[Ebook]) -> Vec<&str> {
+        ebooks.iter().map(|ebook| ebook.title()).collect()
+    }
+
+    #[test]
+    fn select_sorts_by_created_by_default() {
+        let ebooks = vec![
+            ebook("Dracula", "Bram Stoker", (2024, 3, 1)),
+            ebook("Emma", "Jane Austen", (2024, 1, 1)),
+        ];
+        let selected = select(ebooks, &Options::default());
+        assert_eq!(titles(&selected), ["Emma", "Dracula"]);
+    }
+
+    #[test]
+    fn select_sorts_by_series() {
+        let mut first = ebook("The Colour of Magic", "Terry Pratchett", (2024, 1, 1));
+        first.series = Some(Series::new("Discworld".to_owned(), Some(1.0)));
+        let mut second = ebook("The Light Fantastic", "Terry Pratchett", (2024, 1, 1));
+        second.series = Some(Series::new("Discworld".to_owned(), Some(2.0)));
+        let standalone = ebook("Good Omens", "Terry Pratchett", (2024, 1, 1));
+
+        let options = Options {
+            sort: SortKey::Series,
+            ..Options::default()
+        };
+        let selected = select(vec![standalone, second, first], &options);
+        assert_eq!(
+            titles(&selected),
+            ["The Colour of Magic", "The Light Fantastic", "Good Omens"]
+        );
+    }
+
+    #[test]
+    fn select_filters() {
+        let ebooks = vec![
+            ebook("Dracula", "Bram Stoker", (2024, 3, 1)),
+            ebook("Emma", "Jane Austen", (2024, 1, 1)),
+            ebook("Persuasion", "Jane Austen", (2024, 2, 1)),
+        ];
+        let options = Options {
+            author: Some("austen".to_owned()),
+            since: NaiveDate::from_ymd_opt(2024, 2, 1),
+            reverse: true,
+            ..Options::default()
+        };
+        assert_eq!(titles(&select(ebooks, &options)), ["Persuasion"]);
+    }
+
+    #[test]
+    fn parse_column_list() {
+        assert_eq!(
+            parse_columns("title, series,size").unwrap(),
+            [Column::Title, Column::Series, Column::Size]
+        );
+        assert!(parse_columns("title,unknown").is_err());
+    }
+
+    #[test]
+    fn record_values_match_fields() {
+        let mut ebook = ebook("Emma", "Jane Austen", (2024, 1, 1));
+        ebook.series = Some(Series::new("Novels".to_owned(), Some(4.0)));
+        let values = Record::new(&ebook).values();
+        assert_eq!(values.len(), RECORD_FIELDS.len());
+
+        let value = |field: &str| &values[RECORD_FIELDS.iter().position(|f| *f == field).unwrap()];
+        assert_eq!(value("title"), "Emma");
+        assert_eq!(value("creators"), "Jane Austen");
+        assert_eq!(value("series_index"), "4");
+        assert_eq!(value("imported"), "2024-01-01T12:00:00+00:00");
+    }
+}
@@ -3,6 +3,7 @@ use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
 use std::process;
 
+use libri::query::Query;
 use pico_args::Arguments;
 
 #[derive(Debug)]
@@ -12,21 +13,54 @@ enum AppArgs {
         remaining_args: Vec<OsString>,
     },
     Config {},
-    List {},
+    List {
+        options: libri::list::Options,
+    },
+    Reindex {},
+    Reorganize {
+        dry_run: bool,
+    },
     Import {
         path: PathBuf,
         move_books: bool,
         dry_run: bool,
+        fail_fast: bool,
     },
     Upload {
-        dry_run: bool,
+        options: libri::upload::Options,
+    },
+    Cover {
+        book: String,
+        output: Option<PathBuf>,
     },
     Device(Device),
 }
 
 #[derive(Debug)]
 enum Device {
-    List {},
+    List {
+        format: libri::output::Format,
+    },
+    Books {
+        uid: String,
+        format: libri::output::Format,
+    },
+    Annotations {
+        uid: String,
+        format: libri::device::annotations::Format,
+    },
+    Pull {
+        uid: String,
+        move_books: bool,
+        dry_run: bool,
+        fail_fast: bool,
+    },
+    Sync {
+        options: libri::device::sync::Options,
+    },
+    Eject {
+        uid: String,
+    },
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -40,32 +74,60 @@ fn run(args: Arguments, config_dir: Option<&Path>) -> Result<(), Box<dyn Error>>
                 config_dir,
                 remaining_args,
             } => {
-                let config_dir = match &config_dir {
-                    Some(dir) => Some(dir.as_path()),
-                    None => None,
-                };
+                let config_dir = config_dir.as_deref();
                 run(Arguments::from_vec(remaining_args), config_dir)
             }
             AppArgs::Config {} => {
-                libri::config::run(&libri::config::read(config_dir)?);
+                libri::config::run(&read_config(config_dir)?);
                 Ok(())
             }
-            AppArgs::List {} => libri::list::run(&libri::config::read(config_dir)?),
+            AppArgs::List { options } => libri::list::run(&read_config(config_dir)?, &options),
+            AppArgs::Reindex {} => libri::catalog::run(&read_config(config_dir)?),
+            AppArgs::Reorganize { dry_run } => {
+                libri::reorganize::run(&read_config(config_dir)?, dry_run)
+            }
             AppArgs::Import {
                 path,
                 move_books,
                 dry_run,
+                fail_fast,
             } => libri::import::run(
-                &libri::config::read(config_dir)?,
+                &read_config(config_dir)?,
                 &path,
                 move_books,
                 dry_run,
+                fail_fast,
             ),
-            AppArgs::Upload { dry_run } => {
-                libri::upload::run(&libri::config::read(config_dir)?, dry_run)
+            AppArgs::Upload { options } => libri::upload::run(&read_config(config_dir)?, &options),
+            AppArgs::Cover { book, output } => {
+                libri::cover::run(&read_config(config_dir)?, &book, output.as_deref())
             }
             AppArgs::Device(subcommand) => match subcommand {
-                Device::List {} => libri::device::list::run(),
+                Device::List { format } => {
+                    libri::device::list::run(&read_config(config_dir)?, format)
+                }
+                Device::Books { uid, format } => {
+                    libri::device::books::run(&read_config(config_dir)?, &uid, format)
+                }
+                Device::Annotations { uid, format } => {
+                    libri::device::annotations::run(&read_config(config_dir)?, &uid, format)
+                }
+                Device::Pull {
+                    uid,
+                    move_books,
+                    dry_run,
+                    fail_fast,
+                } => libri::device::pull::run(
+                    &read_config(config_dir)?,
+                    &uid,
+                    move_books,
+                    dry_run,
+                    fail_fast,
+                ),
+                Device::Sync { options } => {
+                    libri::device::sync::run(&read_config(config_dir)?, &options)
+                }
+                Device::Eject { uid } => libri::device::eject::run(&read_config(config_dir)?, &uid),
             },
         },
         Err(e) => {
@@ -75,6 +137,19 @@ fn run(args: Arguments, config_dir: Option<&Path>) -> Result<(), Box<dyn Error>>
     }
 }
 
+/// Reads the configuration, warning about device sections which can't apply to any device.
+fn read_config(config_dir: Option<&Path>) -> Result<libri::config::Config, libri::Error> {
+    let config = libri::config::read(config_dir)?;
+    for name in config.unmatched_devices() {
+        eprintln!(
+            "warning: [device \"{}\"] matches no device; name it after a UID or friendly name \
+             shown by 'libri device list'",
+            name
+        );
+    }
+    Ok(config)
+}
+
 fn parse_args(mut args: Arguments) -> Result<AppArgs, Box<dyn Error>> {
     match args.subcommand()?.as_deref() {
         Some("config") => {
@@ -90,18 +165,55 @@ fn parse_args(mut args: Arguments) -> Result<AppArgs, Box<dyn Error>> {
                 println!("{}", LIST_HELP);
                 process::exit(0);
             }
+            let defaults = libri::list::Options::default();
+            let mut options = libri::list::Options {
+                query: None,
+                sort: args.opt_value_from_str("--sort")?.unwrap_or(defaults.sort),
+                reverse: args.contains(["-r", "--reverse"]),
+                author: args.opt_value_from_str("--author")?,
+                title: args.opt_value_from_str("--title")?,
+                since: args.opt_value_from_str("--since")?,
+                until: args.opt_value_from_str("--until")?,
+                columns: args
+                    .opt_value_from_fn("--columns", libri::list::parse_columns)?
+                    .unwrap_or(defaults.columns),
+                format: args
+                    .opt_value_from_str("--format")?
+                    .unwrap_or(defaults.format),
+            };
+            options.query = parse_query(args.finish())?;
+            Ok(AppArgs::List { options })
+        }
+        Some("reindex") => {
+            if args.contains(["-h", "--help"]) {
+                println!("{}", REINDEX_HELP);
+                process::exit(0);
+            }
+            handle_extra_args(args.finish());
+            Ok(AppArgs::Reindex {})
+        }
+        Some("reorganize") => {
+            if args.contains(["-h", "--help"]) {
+                println!("{}", REORGANIZE_HELP);
+                process::exit(0);
+            }
+            let reorganize = AppArgs::Reorganize {
+                dry_run: args.contains("--dry-run"),
+            };
             handle_extra_args(args.finish());
-            Ok(AppArgs::List {})
+            Ok(reorganize)
         }
         Some("import") => {
             if args.contains(["-h", "--help"]) {
                 println!("{}", IMPORT_HELP);
                 process::exit(0);
             }
+            // Flags must be parsed before free arguments.
             let import = AppArgs::Import {
-                path: args.free_from_os_str(parse_path)?,
                 move_books: args.contains(["-m", "--move"]),
                 dry_run: args.contains("--dry-run"),
+                fail_fast: args.contains("--fail-fast"),
+                path: args.free_from_os_str(parse_path)?,
             };
             handle_extra_args(args.finish());
             Ok(import)
@@ -111,11 +223,29 @@ fn parse_args(mut args: Arguments) -> Result<AppArgs, Box<dyn Error>> {
                 println!("{}", UPLOAD_HELP);
                 process::exit(0);
             }
-            let upload = AppArgs::Upload {
+            let mut options = libri::upload::Options {
+                selectors: Vec::new(),
+                device: args.opt_value_from_str("--device")?,
+                to: args.opt_value_from_str("--to")?,
+                latest: args.opt_value_from_str("--latest")?,
+                fit: args.contains("--fit"),
+                eject: args.contains("--eject"),
                 dry_run: args.contains("--dry-run"),
             };
+            options.selectors = parse_selectors(args.finish());
+            Ok(AppArgs::Upload { options })
+        }
+        Some("cover") => {
+            if args.contains(["-h", "--help"]) {
+                println!("{}", COVER_HELP);
+                process::exit(0);
+            }
+            let cover = AppArgs::Cover {
+                output: args.opt_value_from_os_str(["-o", "--output"], parse_path)?,
+                book: args.free_from_str()?,
+            };
             handle_extra_args(args.finish());
-            Ok(upload)
+            Ok(cover)
         }
         Some("device") => {
             if args.contains(["-h", "--help"]) {
@@ -124,8 +254,61 @@ fn parse_args(mut args: Arguments) -> Result<AppArgs, Box<dyn Error>> {
             }
             match args.subcommand()?.as_deref() {
                 Some("list") => {
+                    let list = Device::List {
+                        format: args
+                            .opt_value_from_str("--format")?
+                            .unwrap_or(libri::output::Format::Table),
+                    };
+                    handle_extra_args(args.finish());
+                    Ok(AppArgs::Device(list))
+                }
+                Some("books") => {
+                    let books = Device::Books {
+                        format: args
+                            .opt_value_from_str("--format")?
+                            .unwrap_or(libri::output::Format::Table),
+                        uid: args.free_from_str()?,
+                    };
+                    handle_extra_args(args.finish());
+                    Ok(AppArgs::Device(books))
+                }
+                Some("annotations") => {
+                    let annotations = Device::Annotations {
+                        format: args
+                            .opt_value_from_str("--format")?
+                            .unwrap_or(libri::device::annotations::Format::Markdown),
+                        uid: args.free_from_str()?,
+                    };
+                    handle_extra_args(args.finish());
+                    Ok(AppArgs::Device(annotations))
+                }
+                Some("pull") => {
+                    let pull = Device::Pull {
+                        move_books: args.contains(["-m", "--move"]),
+                        dry_run: args.contains("--dry-run"),
+                        fail_fast: args.contains("--fail-fast"),
+                        uid: args.free_from_str()?,
+                    };
+                    handle_extra_args(args.finish());
+                    Ok(AppArgs::Device(pull))
+                }
+                Some("sync") => {
+                    let mut options = libri::device::sync::Options {
+                        selectors: Vec::new(),
+                        device: args.opt_value_from_str("--device")?,
+                        latest: args.opt_value_from_str("--latest")?,
+                        delete: args.contains("--delete"),
+                        dry_run: args.contains("--dry-run"),
+                    };
+                    options.selectors = parse_selectors(args.finish());
+                    Ok(AppArgs::Device(Device::Sync { options }))
+                }
+                Some("eject") => {
+                    let eject = Device::Eject {
+                        uid: args.free_from_str()?,
+                    };
                     handle_extra_args(args.finish());
-                    Ok(AppArgs::Device(Device::List {}))
+                    Ok(AppArgs::Device(eject))
                 }
                 Some(s) => {
                     Err(format!("unknown subcommand '{}'. See 'libri device --help'", s).into())
@@ -162,6 +345,30 @@ fn parse_path(s: &OsStr) -> Result<PathBuf, &'static str> {
     Ok(s.into())
 }
 
+/// Returns the remaining free arguments, exiting if any of them are unknown flags.
+fn parse_selectors(args: Vec<OsString>) -> Vec<String> {
+    if let Some(flag) = args
+        .iter()
+        .find(|arg| arg.to_string_lossy().starts_with('-'))
+    {
+        eprintln!("unknown argument {:?}", flag);
+        process::exit(1);
+    }
+    args.iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Parses the remaining free arguments as a query. The query can be passed as a single quoted
+/// argument, or as several arguments which are joined with spaces.
+fn parse_query(args: Vec<OsString>) -> Result<Option<Query>, Box<dyn Error>> {
+    let words = parse_selectors(args);
+    if words.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Query::parse(&words.join(" "))?))
+}
+
 fn handle_extra_args(args: Vec<OsString>) {
     if !args.is_empty() {
         eprintln!("unknown argument {:?}", args[0]);
@@ -184,8 +391,11 @@ OPTIONS:
 SUBCOMMANDS:
   config                View and edit the configuration
   list                  List books in the library
+  reindex               Rebuild the library catalog
+  reorganize            Move books to match the configured path template
   import                Import new books
   upload                Upload books to connected eReaders
+  cover                 Export the cover of a book
   device                Manage hardware devices and their content";
 
 const CONFIG_HELP: &str = "\
@@ -203,11 +413,50 @@ libri-list
 List books in the library
 
 USAGE:
-  libri list
+  libri list [OPTIONS] [QUERY]
+
+FLAGS:
+  -h, --help            Print help information
+  -r, --reverse         List books in reverse order
+
+OPTIONS:
+  --sort KEY            Sort books by title, author, created, series or size (default: created)
+  --author TEXT         Only list books by authors whose name contains TEXT
+  --title TEXT          Only list books whose title contains TEXT
+  --since DATE          Only list books imported on or after DATE (YYYY-MM-DD)
+  --until DATE          Only list books imported on or before DATE (YYYY-MM-DD)
+  --columns LIST        Comma-separated columns to show (default: title,author,created). Available
+                        columns are title, author, created, series, size, identifier, language,
+                        publisher, date, isbn and path
+  --format FORMAT       Output format: table, json, csv or tsv (default: table). Formats other
+                        than table include every metadata field
+
+ARGS:
+  QUERY                 Only list books matching the query, such as
+                        'author:dickens AND (tag:classic OR series:\"Discworld\")'. Fields are
+                        title, author, series, tag, language, publisher, isbn, id, added and year";
+
+const REINDEX_HELP: &str = "\
+libri-reindex
+Rebuild the library catalog from scratch by parsing every book in the library
+
+USAGE:
+  libri reindex
 
 FLAGS:
   -h, --help            Print help information";
 
+const REORGANIZE_HELP: &str = "\
+libri-reorganize
+Move books to the location described by the configured path template
+
+USAGE:
+  libri reorganize
+
+FLAGS:
+  -h, --help            Print help information
+  --dry-run             Print the planned moves without making any changes to the file system";
+
 const IMPORT_HELP: &str = "\
 libri-import
 Import new books
@@ -218,6 +467,7 @@ USAGE:
 FLAGS:
   -h, --help            Print help information
   --dry-run             Run without making any changes to the file system
+  --fail-fast           Stop importing at the first book which fails to import
 
 ARGS:
   PATH                  Path to import directory";
@@ -227,11 +477,42 @@ libri-upload
 Upload books to connected eReaders
 
 USAGE:
-  libri upload
+  libri upload [OPTIONS] [BOOK]...
+
+FLAGS:
+  -h, --help            Print help information
+  --dry-run             Run without making any changes to the file system
+  --eject               Eject the eReaders once the books are uploaded
+  --fit                 Upload as many of the selected books as fit when an eReader is short on
+                        space, instead of skipping the eReader
+
+OPTIONS:
+  --device UID          Only upload to the device with the given UID or name (see
+                        'libri device list')
+  --to TARGET           Upload to a directory, or to a profile which sets mount_path, instead of
+                        the connected eReaders
+  --latest N            Only upload the N most recently imported of the selected books
+
+ARGS:
+  BOOK                  Identifier of a book, or a query selecting books (see 'libri list --help').
+                        Arguments which aren't identifiers are joined into a single query. Every
+                        book in the library is uploaded when no books are given";
+
+const COVER_HELP: &str = "\
+libri-cover
+Export the cover image of a book in the library
+
+USAGE:
+  libri cover [OPTIONS] <BOOK>
 
 FLAGS:
   -h, --help            Print help information
-  --dry-run             Run without making any changes to the file system";
+
+OPTIONS:
+  -o, --output PATH     File or directory to save the cover to (default: the current directory)
+
+ARGS:
+  BOOK                  Identifier of the book, or part of its title";
 
 const DEVICE_HELP: &str = "\
 libri-device
@@ -244,4 +525,31 @@ FLAGS:
   -h, --help            Print help information
 
 SUBCOMMANDS:
-  list                  List connected eReaders supported by libri";
+  list                  List connected eReaders supported by libri
+  books <UID>           List the books on an eReader and whether they are in the library
+  annotations <UID>     Export reading progress, highlights and notes from an eReader
+  pull <UID>            Import books from an eReader which are missing from the library
+  sync                  Copy selected books to eReaders and remove books no longer selected
+  eject <UID>           Flush pending writes and unmount an eReader so it can be unplugged
+
+OPTIONS (list, books):
+  --format FORMAT       Output format: table, json, csv or tsv (default: table)
+
+OPTIONS (annotations):
+  --format FORMAT       Output format: markdown or json (default: markdown)
+
+OPTIONS (pull):
+  -m, --move            Move books off the eReader instead of copying them
+  --dry-run             Run without making any changes to the file system
+  --fail-fast           Stop importing at the first book which fails to import
+
+OPTIONS (sync):
+  --device UID          Only sync the device with the given UID or name
+  --latest N            Only keep the N most recently imported of the selected books
+  --delete              Remove books placed by libri which are no longer selected
+  --dry-run             Run without making any changes to the file system
+
+ARGS (sync):
+  BOOK                  Identifier of a book, or a query selecting books (see 'libri list --help').
+                        Arguments which aren't identifiers are joined into a single query. Every
+                        book in the library is selected when no books are given";
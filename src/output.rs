@@ -0,0 +1,64 @@
+//! Output formats shared by the commands which list books and devices.
+
+use std::error::Error;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// The format command output is printed in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// A table meant to be read by humans. The layout may change between releases.
+    Table,
+    /// A JSON array with one object per record.
+    Json,
+    /// Comma-separated values with a header row.
+    Csv,
+    /// Tab-separated values with a header row.
+    Tsv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(format!(
+                "unknown format '{}' (expected one of table, json, csv, tsv)",
+                s
+            )),
+        }
+    }
+}
+
+/// Prints the records as a JSON array.
+pub fn write_json<T: Serialize>(records: &[T]) -> Result<(), Box<dyn Error>> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, records)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// Prints a header row followed by one row per record, separated by commas or tabs depending on
+/// `format`.
+pub fn write_delimited(
+    format: Format,
+    header: &[&str],
+    rows: &[Vec<String>],
+) -> Result<(), Box<dyn Error>> {
+    let delimiter = if format == Format::Tsv { b'\t' } else { b',' };
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(io::stdout());
+    writer.write_record(header)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
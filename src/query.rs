@@ -0,0 +1,603 @@
+//! A small query language for selecting books.
+//!
+//! A query is made up of terms combined with `AND`, `OR` and `NOT`, grouped with parentheses.
+//! Terms next to each other are implicitly combined with `AND`. A term is either a bare word,
+//! which matches books whose title or author contains it, or a `field:value` pair such as
+//! `author:dickens`. Values containing spaces can be quoted, as in `series:"Discworld"`.
+//!
+//! Text fields match values containing the given text, ignoring case, or only equal values when
+//! written as `field:=value`. The `added` and `year` fields can also be compared with `>`, `>=`,
+//! `<` and `<=`, as in `added:>2024-01-01`.
+
+use std::fmt;
+
+use chrono::{Local, NaiveDate};
+
+use crate::Ebook;
+
+/// Fields which can be used in terms, and the kind of value they hold.
+const FIELDS: &[(&str, Field)] = &[
+    ("title", Field::Title),
+    ("author", Field::Author),
+    ("series", Field::Series),
+    ("tag", Field::Tag),
+    ("language", Field::Language),
+    ("publisher", Field::Publisher),
+    ("isbn", Field::Isbn),
+    ("id", Field::Identifier),
+    ("added", Field::Added),
+    ("year", Field::Year),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    /// Matches either the title or the author, used for bare words.
+    Any,
+    Title,
+    Author,
+    Series,
+    Tag,
+    Language,
+    Publisher,
+    Isbn,
+    Identifier,
+    Added,
+    Year,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Contains,
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Text(String),
+    Date(NaiveDate),
+    Number(u32),
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term {
+        field: Field,
+        comparison: Comparison,
+        value: Value,
+    },
+}
+
+/// A parsed query, which can be used as a predicate over ebooks.
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    source: String,
+    expr: Expr,
+}
+
+/// An error in the syntax of a query.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    query: String,
+    /// The offset of the offending token, in characters.
+    position: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at position {}\n  {}\n  {}^",
+            self.message,
+            self.position + 1,
+            self.query,
+            " ".repeat(self.position)
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    /// A bare word or a `field:value` pair. The field and value are kept as written.
+    Term {
+        field: Option<(String, usize)>,
+        comparison: Option<Comparison>,
+        value: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    /// The offset of the token in the query, in characters.
+    position: usize,
+}
+
+impl Query {
+    /// Parses a query, returning an error pointing at the offending token if it is malformed.
+    pub fn parse(source: &str) -> Result<Query, ParseError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            tokens: &tokens,
+            next: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error(token.position, "unexpected ')'"));
+        }
+        Ok(Query {
+            source: source.to_owned(),
+            expr,
+        })
+    }
+
+    /// Returns whether the ebook is selected by the query.
+    pub fn matches(&self, ebook: &Ebook) -> bool {
+        evaluate(&self.expr, ebook)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn error(source: &str, position: usize, message: impl Into<String>) -> ParseError {
+    ParseError {
+        query: source.to_owned(),
+        position,
+        message: message.into(),
+    }
+}
+
+/// Splits a query into tokens.
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let kind = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::LeftParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RightParen
+            }
+            _ => {
+                let (word, quoted) = read_value(source, &chars, &mut i, true)?;
+                if quoted {
+                    TokenKind::Term {
+                        field: None,
+                        comparison: None,
+                        value: word,
+                    }
+                } else if i < chars.len() && chars[i] == ':' {
+                    i += 1;
+                    let comparison = read_comparison(&chars, &mut i);
+                    let value_position = i;
+                    let (value, _) = read_value(source, &chars, &mut i, false)?;
+                    if value.is_empty() {
+                        return Err(error(source, value_position, "expected a value"));
+                    }
+                    TokenKind::Term {
+                        field: Some((word, start)),
+                        comparison,
+                        value,
+                    }
+                } else {
+                    match word.as_str() {
+                        "AND" => TokenKind::And,
+                        "OR" => TokenKind::Or,
+                        "NOT" => TokenKind::Not,
+                        _ => TokenKind::Term {
+                            field: None,
+                            comparison: None,
+                            value: word,
+                        },
+                    }
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            position: start,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Reads a bare or quoted word starting at `i`, returning the word and whether it was quoted. A
+/// bare word ends at whitespace or a parenthesis, or at a colon if `stop_at_colon` is set.
+fn read_value(
+    source: &str,
+    chars: &[char],
+    i: &mut usize,
+    stop_at_colon: bool,
+) -> Result<(String, bool), ParseError> {
+    let mut word = String::new();
+    if *i < chars.len() && chars[*i] == '"' {
+        let start = *i;
+        *i += 1;
+        loop {
+            match chars.get(*i) {
+                Some('"') => {
+                    *i += 1;
+                    return Ok((word, true));
+                }
+                Some('\\') if chars.get(*i + 1).is_some() => {
+                    word.push(chars[*i + 1]);
+                    *i += 2;
+                }
+                Some(c) => {
+                    word.push(*c);
+                    *i += 1;
+                }
+                None => return Err(error(source, start, "unterminated quote")),
+            }
+        }
+    }
+    while let Some(&c) = chars.get(*i) {
+        if c.is_whitespace() || c == '(' || c == ')' || (stop_at_colon && c == ':') {
+            break;
+        }
+        word.push(c);
+        *i += 1;
+    }
+    Ok((word, false))
+}
+
+/// Reads an optional comparison operator starting at `i`.
+fn read_comparison(chars: &[char], i: &mut usize) -> Option<Comparison> {
+    let (comparison, len) = match (chars.get(*i), chars.get(*i + 1)) {
+        (Some('>'), Some('=')) => (Comparison::GreaterOrEqual, 2),
+        (Some('<'), Some('=')) => (Comparison::LessOrEqual, 2),
+        (Some('>'), _) => (Comparison::Greater, 1),
+        (Some('<'), _) => (Comparison::Less, 1),
+        (Some('='), _) => (Comparison::Equal, 1),
+        _ => return None,
+    };
+    *i += len;
+    Some(comparison)
+}
+
+/// A recursive descent parser over the tokens of a query.
+struct Parser<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn error(&self, position: usize, message: impl Into<String>) -> ParseError {
+        error(self.source, position, message)
+    }
+
+    /// Returns the position just past the end of the query, used for errors at the end.
+    fn end(&self) -> usize {
+        self.source.chars().count()
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.peek().is_some_and(|token| token.kind == TokenKind::Or) {
+            self.next += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.not()?;
+        loop {
+            match self.peek().map(|token| &token.kind) {
+                Some(TokenKind::And) => self.next += 1,
+                // Terms next to each other are implicitly combined with AND.
+                Some(TokenKind::LeftParen | TokenKind::Not | TokenKind::Term { .. }) => (),
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self
+            .peek()
+            .is_some_and(|token| token.kind == TokenKind::Not)
+        {
+            self.next += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err(self.error(self.end(), "expected a search term")),
+        };
+        self.next += 1;
+        match &token.kind {
+            TokenKind::LeftParen => {
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token {
+                        kind: TokenKind::RightParen,
+                        ..
+                    }) => {
+                        self.next += 1;
+                        Ok(expr)
+                    }
+                    Some(token) => Err(self.error(token.position, "expected ')'")),
+                    None => Err(self.error(self.end(), "expected ')'")),
+                }
+            }
+            TokenKind::Term {
+                field,
+                comparison,
+                value,
+            } => self.term(field.as_ref(), *comparison, value, token.position),
+            TokenKind::RightParen => Err(self.error(token.position, "unexpected ')'")),
+            TokenKind::And => Err(self.error(token.position, "unexpected 'AND'")),
+            TokenKind::Or => Err(self.error(token.position, "unexpected 'OR'")),
+            TokenKind::Not => unreachable!(),
+        }
+    }
+
+    /// Checks a term against the field it refers to, parsing its value.
+    fn term(
+        &self,
+        field: Option<&(String, usize)>,
+        comparison: Option<Comparison>,
+        value: &str,
+        position: usize,
+    ) -> Result<Expr, ParseError> {
+        let (field, name) = match field {
+            Some((name, field_position)) => {
+                let field = FIELDS
+                    .iter()
+                    .find(|(known, _)| known == name)
+                    .map(|(_, field)| *field)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                        self.error(
+                            *field_position,
+                            format!(
+                                "unknown field '{}' (expected one of {})",
+                                name,
+                                names.join(", ")
+                            ),
+                        )
+                    })?;
+                (field, name.as_str())
+            }
+            None => (Field::Any, ""),
+        };
+
+        let value = match field {
+            Field::Added => {
+                Value::Date(NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+                    self.error(
+                        position,
+                        format!("invalid date '{}' (expected YYYY-MM-DD)", value),
+                    )
+                })?)
+            }
+            Field::Year => Value::Number(
+                value
+                    .parse()
+                    .map_err(|_| self.error(position, format!("invalid year '{}'", value)))?,
+            ),
+            _ => Value::Text(value.to_lowercase()),
+        };
+        let comparison = match (comparison, &value) {
+            (None, Value::Text(_)) => Comparison::Contains,
+            (None, _) => Comparison::Equal,
+            (Some(Comparison::Equal), _) | (Some(_), Value::Date(_) | Value::Number(_)) => {
+                comparison.unwrap()
+            }
+            (Some(_), Value::Text(_)) => {
+                return Err(self.error(
+                    position,
+                    format!("field '{}' can only be compared with '='", name),
+                ))
+            }
+        };
+        Ok(Expr::Term {
+            field,
+            comparison,
+            value,
+        })
+    }
+}
+
+fn evaluate(expr: &Expr, ebook: &Ebook) -> bool {
+    match expr {
+        Expr::And(a, b) => evaluate(a, ebook) && evaluate(b, ebook),
+        Expr::Or(a, b) => evaluate(a, ebook) || evaluate(b, ebook),
+        Expr::Not(expr) => !evaluate(expr, ebook),
+        Expr::Term {
+            field,
+            comparison,
+            value,
+        } => match value {
+            Value::Text(text) => text_values(ebook, *field).iter().any(|value| {
+                let value = value.to_lowercase();
+                match comparison {
+                    Comparison::Equal => value == *text,
+                    _ => value.contains(text.as_str()),
+                }
+            }),
+            Value::Date(date) => compare(
+                ebook.imported.with_timezone(&Local).date_naive(),
+                *comparison,
+                *date,
+            ),
+            Value::Number(number) => ebook
+                .year()
+                .and_then(|year| year.parse().ok())
+                .is_some_and(|year: u32| compare(year, *comparison, *number)),
+        },
+    }
+}
+
+/// Returns the values of a text field of the ebook. Fields can have several values, such as the
+/// names of each author.
+fn text_values(ebook: &Ebook, field: Field) -> Vec<&str> {
+    match field {
+        Field::Any => {
+            let mut values = vec![ebook.title()];
+            values.extend(ebook.creators.iter().map(|creator| creator.name()));
+            values
+        }
+        Field::Title => {
+            let mut values = vec![ebook.title()];
+            values.extend(ebook.subtitle());
+            values
+        }
+        Field::Author => ebook
+            .creators
+            .iter()
+            .map(|creator| creator.name())
+            .collect(),
+        Field::Series => ebook
+            .series()
+            .map(|series| series.name())
+            .into_iter()
+            .collect(),
+        Field::Tag => ebook.subjects.iter().map(String::as_str).collect(),
+        Field::Language => ebook.languages.iter().map(String::as_str).collect(),
+        Field::Publisher => ebook.publisher().into_iter().collect(),
+        Field::Isbn => ebook.isbn().into_iter().collect(),
+        Field::Identifier => ebook
+            .identifiers
+            .iter()
+            .map(|identifier| identifier.value())
+            .chain([ebook.identifier()])
+            .collect(),
+        Field::Added | Field::Year => Vec::new(),
+    }
+}
+
+fn compare<T: PartialOrd>(value: T, comparison: Comparison, other: T) -> bool {
+    match comparison {
+        Comparison::Contains | Comparison::Equal => value == other,
+        Comparison::Less => value < other,
+        Comparison::LessOrEqual => value <= other,
+        Comparison::Greater => value > other,
+        Comparison::GreaterOrEqual => value >= other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::EbookBuilder;
+    use crate::Series;
+
+    fn ebook() -> Ebook {
+        let mut ebook = EbookBuilder::new("Great Expectations", "Charles Dickens")
+            .imported(2024, 6, 1)
+            .build();
+        ebook.subjects = vec!["Classic".to_owned(), "Fiction".to_owned()];
+        ebook.date = Some("1861-08-01".to_owned());
+        ebook
+    }
+
+    fn matches(query: &str) -> bool {
+        Query::parse(query).unwrap().matches(&ebook())
+    }
+
+    #[test]
+    fn match_terms() {
+        assert!(matches("dickens"));
+        assert!(matches("author:DICKENS"));
+        assert!(!matches("author:=dickens"));
+        assert!(matches("author:=\"charles dickens\""));
+        assert!(matches("tag:classic"));
+        assert!(!matches("series:discworld"));
+        assert!(matches("added:>2024-01-01"));
+        assert!(!matches("added:<2024-06-01"));
+        assert!(matches("added:2024-06-01"));
+        assert!(matches("year:<=1900"));
+    }
+
+    #[test]
+    fn match_boolean_expressions() {
+        assert!(matches(
+            "author:dickens AND (tag:classic OR series:\"Discworld\") AND added:>2024-01-01"
+        ));
+        assert!(matches("author:dickens tag:fiction"));
+        assert!(!matches("author:dickens NOT tag:fiction"));
+        assert!(matches("series:discworld OR title:great"));
+        assert!(matches("NOT (series:discworld OR tag:poetry)"));
+
+        let mut ebook = ebook();
+        ebook.series = Some(Series::new("Discworld".to_owned(), None));
+        assert!(Query::parse("series:disc").unwrap().matches(&ebook));
+    }
+
+    #[test]
+    fn parse_errors_point_at_token() {
+        let error = |query: &str| {
+            let error = Query::parse(query).unwrap_err();
+            (error.position, error.message)
+        };
+        assert_eq!(
+            error("author:dickens AND (tag:classic"),
+            (31, "expected ')'".to_owned())
+        );
+        assert_eq!(error("tag:classic)"), (11, "unexpected ')'".to_owned()));
+        assert_eq!(error("OR tag:classic"), (0, "unexpected 'OR'".to_owned()));
+        assert_eq!(error("title:x AND colour:red").0, 12,);
+        assert_eq!(
+            error("added:>2024-13-01"),
+            (
+                0,
+                "invalid date '2024-13-01' (expected YYYY-MM-DD)".to_owned()
+            )
+        );
+        assert_eq!(
+            error("author:>dickens"),
+            (0, "field 'author' can only be compared with '='".to_owned())
+        );
+        assert_eq!(error("title:\"open"), (6, "unterminated quote".to_owned()));
+        assert_eq!(error("author:"), (7, "expected a value".to_owned()));
+        assert_eq!(error(""), (0, "expected a search term".to_owned()));
+    }
+
+    #[test]
+    fn display_parse_error() {
+        let error = Query::parse("tag:classic)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unexpected ')' at position 12\n  tag:classic)\n             ^"
+        );
+    }
+}
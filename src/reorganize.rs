@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::catalog::Catalog;
+use crate::common;
+use crate::config;
+use crate::Ebook;
+
+struct ReorganizeStats {
+    moved: u32,
+    unchanged: u32,
+    skipped: u32,
+    elapsed: Duration,
+}
+
+impl fmt::Display for ReorganizeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "moved {}; unchanged {}; skipped {}; finished in {:.2}s",
+            self.moved,
+            self.unchanged,
+            self.skipped,
+            self.elapsed.as_secs_f32()
+        )
+    }
+}
+
+/// Moves every book in the library to the location described by the configured path template.
+pub fn run(config: &config::Config, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let mut stats = ReorganizeStats {
+        moved: 0,
+        unchanged: 0,
+        skipped: 0,
+        elapsed: Duration::ZERO,
+    };
+    let start = Instant::now();
+    let mut catalog = Catalog::open(&config.library)?;
+    let mut claimed = HashSet::new();
+
+    for ebook in catalog.ebooks().to_vec() {
+        let relative = match config.path_template.render(&ebook) {
+            Ok(relative) => relative,
+            Err(e) => {
+                println!("skipping \"{}\" -- {}", ebook.title, e);
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        let destination = config.library.join(&relative);
+        if destination == ebook.path {
+            stats.unchanged += 1;
+            continue;
+        }
+        // Track the destinations of the books already handled, since they won't exist on a dry
+        // run.
+        if destination.exists() || claimed.contains(&destination) {
+            println!(
+                "skipping \"{}\" -- '{}' already exists",
+                ebook.title,
+                relative.display()
+            );
+            stats.skipped += 1;
+            continue;
+        }
+        let sidecars = match sidecar_moves(&ebook, &destination, &config.library) {
+            Ok(sidecars) => sidecars,
+            Err(e) => {
+                println!("skipping \"{}\" -- {}", ebook.title, e);
+                stats.skipped += 1;
+                continue;
+            }
+        };
+        if let Some((_, target)) = sidecars.iter().find(|(_, target)| target.exists()) {
+            println!(
+                "skipping \"{}\" -- '{}' already exists",
+                ebook.title,
+                target
+                    .strip_prefix(&config.library)
+                    .unwrap_or(target)
+                    .display()
+            );
+            stats.skipped += 1;
+            continue;
+        }
+        claimed.insert(destination.clone());
+
+        if !dry_run {
+            if let Err(e) = move_ebook(&ebook, &destination, &sidecars, &config.library) {
+                // Record the books moved so far before bailing out.
+                catalog.save()?;
+                return Err(format!("failed to move \"{}\": {}", ebook.title, e).into());
+            }
+            let mut moved = catalog.remove(&ebook.path).unwrap();
+            moved.path = destination;
+            catalog.insert(moved);
+        }
+        println!("moved \"{}\" to '{}'", ebook.title, relative.display());
+        stats.moved += 1;
+    }
+    if !dry_run {
+        catalog.save()?;
+    }
+
+    stats.elapsed = start.elapsed();
+    print!("\n{}", stats);
+    if dry_run {
+        print!("; dry run");
+    }
+    println!();
+    Ok(())
+}
+
+/// Moves an ebook to `destination` and each of its sidecar files (such as covers) to the target
+/// paired with it, then removes any directories left empty by the move.
+fn move_ebook(
+    ebook: &Ebook,
+    destination: &Path,
+    sidecars: &[(PathBuf, PathBuf)],
+    library: &Path,
+) -> io::Result<()> {
+    fs::create_dir_all(destination.parent().unwrap())?;
+    common::move_file(&ebook.path, destination)?;
+    for (sidecar, target) in sidecars {
+        common::move_file(sidecar, target)?;
+    }
+    common::remove_empty_dirs(ebook.path.parent().unwrap(), library)
+}
+
+/// Returns the sidecar files of an ebook along with where they move to when the ebook moves to
+/// `destination`. Sidecars are named after their book (`Dracula.cover.jpg`) in directories shared
+/// with other files, so they can't be mistaken for belonging to another book, and keep their
+/// plain name (`cover.jpg`) in a directory of the book's own. When the ebook joins a book which had
+/// the directory to itself, the sidecars of that book are renamed after it as well.
+fn sidecar_moves(
+    ebook: &Ebook,
+    destination: &Path,
+    library: &Path,
+) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let sidecars = sidecar_files(&ebook.path, library)?;
+    let destination_dir = destination.parent().unwrap();
+    let mut others = Vec::new();
+    if destination_dir.is_dir() {
+        for entry in fs::read_dir(destination_dir)? {
+            let path = entry?.path();
+            if !path.is_dir()
+                && path != ebook.path
+                && !sidecars.iter().any(|(sidecar, _)| *sidecar == path)
+            {
+                others.push(path);
+            }
+        }
+    }
+
+    let mut moves = Vec::new();
+    let mut add = |path: PathBuf, book: &Path, name: OsString, shared: bool| {
+        let mut target_name = OsString::new();
+        if shared {
+            target_name.push(book.file_stem().unwrap());
+            target_name.push(".");
+        }
+        target_name.push(name);
+        let target = destination_dir.join(target_name);
+        if target != path {
+            moves.push((path, target));
+        }
+    };
+    for (sidecar, name) in sidecars {
+        add(sidecar, destination, name, !others.is_empty());
+    }
+    let books: Vec<&PathBuf> = others.iter().filter(|path| is_ebook(path)).collect();
+    if let [book] = books.as_slice() {
+        for (sidecar, name) in sidecar_files(book, library)? {
+            add(sidecar, book, name, true);
+        }
+    }
+    Ok(moves)
+}
+
+fn is_ebook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "epub")
+}
+
+/// Returns the files stored alongside the ebook at `path` which belong to it, each with its name
+/// minus any prefix naming the book. When the ebook has a directory to itself, every other file in
+/// it belongs to the book. In a directory shared with other books, or at the root of the library,
+/// only the files named after the book do.
+fn sidecar_files(path: &Path, library: &Path) -> io::Result<Vec<(PathBuf, OsString)>> {
+    let dir = path.parent().unwrap();
+    let mut prefix = path.file_stem().unwrap().to_os_string();
+    prefix.push(".");
+    let prefix = prefix.to_string_lossy();
+
+    let mut files = Vec::new();
+    let mut shared = dir == library;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?.path();
+        if entry == path || entry.is_dir() {
+            continue;
+        }
+        if is_ebook(&entry) {
+            shared = true;
+            continue;
+        }
+        files.push(entry);
+    }
+
+    let mut sidecars = Vec::new();
+    for path in files {
+        let file_name = path.file_name().unwrap();
+        let unprefixed = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(prefix.as_ref()))
+            .filter(|name| !name.is_empty());
+        match unprefixed {
+            Some(name) => sidecars.push((path.clone(), name.into())),
+            None if !shared => sidecars.push((path.clone(), file_name.to_os_string())),
+            None => {}
+        }
+    }
+    Ok(sidecars)
+}
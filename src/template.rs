@@ -0,0 +1,372 @@
+//! Templates describing where books are stored, relative to the root of the library or device.
+//!
+//! A template is a path containing placeholders in curly braces, which are replaced by the
+//! metadata of each book. For example, the default template `{author}/{title}/{title}.{ext}`
+//! stores each book in a directory named after its title, inside a directory named after its
+//! author. Numeric values can be zero-padded to a minimum width with `{field:0N}`.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::common;
+use crate::{Ebook, Error};
+
+/// The template used when none is configured, matching the original library layout.
+pub const DEFAULT_TEMPLATE: &str = "{author}/{title}/{title}.{ext}";
+
+/// Characters dropped when they separate a placeholder without a value from the rest of the path.
+const SEPARATORS: &[char] = &[' ', '-', '_', ','];
+
+/// Metadata fields which can be used as placeholders.
+pub const FIELDS: &[&str] = &[
+    "identifier",
+    "title",
+    "title_sort",
+    "subtitle",
+    "author",
+    "author_sort",
+    "series",
+    "series_index",
+    "language",
+    "publisher",
+    "date",
+    "year",
+    "isbn",
+    "ext",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field { name: String, width: Option<usize> },
+}
+
+/// A parsed path template.
+#[derive(Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    /// The segments making up each component of the path.
+    components: Vec<Vec<Segment>>,
+}
+
+impl Template {
+    /// Parses a template, returning an error if it is malformed or references unknown fields.
+    pub fn parse(source: &str) -> Result<Template, Error> {
+        let invalid = |message: String| {
+            Error::InvalidConfig(format!("invalid path template '{}': {}", source, message))
+        };
+
+        if source.starts_with('/') {
+            return Err(invalid("template must be a relative path".to_owned()));
+        }
+
+        let mut components = Vec::new();
+        for component in source.split('/') {
+            if component.is_empty() {
+                return Err(invalid(
+                    "template contains an empty path component".to_owned(),
+                ));
+            }
+            if component == "." || component == ".." {
+                return Err(invalid(format!(
+                    "'{}' is not allowed in a template",
+                    component
+                )));
+            }
+
+            let mut segments = Vec::new();
+            let mut rest = component;
+            while let Some(start) = rest.find(['{', '}']) {
+                if rest[start..].starts_with('}') {
+                    return Err(invalid("unmatched '}'".to_owned()));
+                }
+                if start > 0 {
+                    segments.push(Segment::Literal(rest[..start].to_owned()));
+                }
+                let end = match rest[start..].find('}') {
+                    Some(end) => start + end,
+                    None => return Err(invalid("unmatched '{'".to_owned())),
+                };
+                segments.push(parse_field(&rest[start + 1..end]).map_err(invalid)?);
+                rest = &rest[end + 1..];
+            }
+            if !rest.is_empty() {
+                segments.push(Segment::Literal(rest.to_owned()));
+            }
+            components.push(segments);
+        }
+        // Books are only recognized by their extension, so a book stored without one would drop
+        // out of the library.
+        if !source.ends_with(".{ext}") {
+            return Err(invalid("template must end in '.{ext}'".to_owned()));
+        }
+
+        Ok(Template {
+            source: source.to_owned(),
+            components,
+        })
+    }
+
+    /// Returns the path of the given ebook, relative to the library root.
+    ///
+    /// Values are sanitized so they never introduce additional path components. Components whose
+    /// placeholders are all empty are omitted, and separators next to an empty placeholder (such
+    /// as the " - " in `{series_index:02} - {title}`) are dropped. Returns an error if the book is
+    /// missing the metadata needed to name it.
+    pub fn render(&self, ebook: &Ebook) -> Result<PathBuf, Error> {
+        let mut path = PathBuf::new();
+        let mut file_name = String::new();
+        for segments in &self.components {
+            let mut component = String::new();
+            // Where the last literal starts in the component, if it was the previous segment.
+            let mut literal_start = None;
+            let mut after_empty = false;
+            for segment in segments {
+                match segment {
+                    Segment::Literal(text) => {
+                        literal_start = Some(component.len());
+                        if after_empty {
+                            component.push_str(text.trim_start_matches(SEPARATORS));
+                        } else {
+                            component.push_str(text);
+                        }
+                        after_empty = false;
+                    }
+                    Segment::Field { name, width } => {
+                        let value = field(ebook, name).unwrap_or_default();
+                        if value.is_empty() {
+                            if let Some(start) = literal_start {
+                                let literal = component[start..].trim_end_matches(SEPARATORS);
+                                component.truncate(start + literal.len());
+                            }
+                            after_empty = true;
+                        } else {
+                            component.push_str(&pad(&sanitize(&value), *width));
+                            after_empty = false;
+                        }
+                        literal_start = None;
+                    }
+                }
+            }
+            if component == "." || component == ".." {
+                return Err(self.missing_metadata());
+            }
+            if !component.is_empty() {
+                path.push(&component);
+            }
+            file_name = component;
+        }
+
+        // The file name can't be omitted, and a name made up of nothing but the extension would be
+        // hidden, and shared by every other book missing the same metadata.
+        let extension = field(ebook, "ext").unwrap_or_default();
+        let stem = file_name.strip_suffix(&extension).unwrap_or(&file_name);
+        if stem.is_empty() || stem == "." {
+            return Err(self.missing_metadata());
+        }
+        Ok(path)
+    }
+
+    fn missing_metadata(&self) -> Error {
+        Error::MissingMetadata(format!("to fill in path template '{}'", self.source))
+    }
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        Template::parse(DEFAULT_TEMPLATE).unwrap()
+    }
+}
+
+impl fmt::Debug for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Parses the contents of a placeholder, such as `title` or `series_index:02`.
+fn parse_field(contents: &str) -> Result<Segment, String> {
+    let (name, spec) = match contents.split_once(':') {
+        Some((name, spec)) => (name, Some(spec)),
+        None => (contents, None),
+    };
+    if !FIELDS.contains(&name) {
+        return Err(format!("unknown field '{}'", name));
+    }
+
+    let width = match spec {
+        Some(spec) => match spec.strip_prefix('0').map(str::parse::<usize>) {
+            Some(Ok(width)) => Some(width),
+            _ => return Err(format!("invalid format '{}' for field '{}'", spec, name)),
+        },
+        None => None,
+    };
+    Ok(Segment::Field {
+        name: name.to_owned(),
+        width,
+    })
+}
+
+/// Returns the value of the named metadata field of an ebook, if it has one.
+fn field(ebook: &Ebook, name: &str) -> Option<String> {
+    match name {
+        "identifier" => Some(ebook.identifier.clone()),
+        "title" => Some(ebook.title.clone()),
+        "title_sort" => Some(ebook.title_sort().to_owned()),
+        "subtitle" => ebook.subtitle.clone(),
+        "author" => Some(ebook.author().to_owned()),
+        "author_sort" => Some(ebook.author_sort().to_owned()),
+        "series" => ebook.series.as_ref().map(|series| series.name.clone()),
+        "series_index" => ebook
+            .series
+            .as_ref()
+            .and_then(|series| series.index)
+            .map(common::format_number),
+        "language" => ebook.languages.first().cloned(),
+        "publisher" => ebook.publisher.clone(),
+        "date" => ebook.date.clone(),
+        "year" => ebook.year().map(str::to_owned),
+        "isbn" => ebook.isbn().map(str::to_owned),
+        "ext" => Some(
+            ebook
+                .path
+                .extension()
+                .map_or("epub".to_owned(), |ext| ext.to_string_lossy().into_owned()),
+        ),
+        _ => None,
+    }
+}
+
+/// Replaces characters which would otherwise be interpreted as path separators.
+fn sanitize(value: &str) -> String {
+    common::sanitize(&value.replace(['/', '\\'], "_"))
+}
+
+/// Pads the integer part of numeric values with zeros to the given width. Non-numeric values are
+/// returned unmodified.
+fn pad(value: &str, width: Option<usize>) -> String {
+    let width = match width {
+        Some(width) => width,
+        None => return value.to_owned(),
+    };
+    let integer = value.split('.').next().unwrap_or_default();
+    if integer.is_empty() || !integer.chars().all(|c| c.is_ascii_digit()) {
+        return value.to_owned();
+    }
+    format!(
+        "{}{}",
+        "0".repeat(width.saturating_sub(integer.len())),
+        value
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::fixtures::EbookBuilder;
+    use crate::Series;
+
+    fn ebook(title: &str, author: &str) -> Ebook {
+        EbookBuilder::new(title, author).build()
+    }
+
+    #[test]
+    fn render_default_template() {
+        let template = Template::default();
+        assert_eq!(
+            template.render(&ebook("Dracula", "Bram Stoker")).unwrap(),
+            PathBuf::from("Bram Stoker/Dracula/Dracula.epub")
+        );
+    }
+
+    #[test]
+    fn render_sanitizes_values() {
+        let template = Template::parse("{author}/{title}.{ext}").unwrap();
+        assert_eq!(
+            template
+                .render(&ebook("Either/Or: A Fragment", "Søren Kierkegaard"))
+                .unwrap(),
+            PathBuf::from("Søren Kierkegaard/Either_Or_ A Fragment.epub")
+        );
+    }
+
+    #[test]
+    fn render_omits_empty_components() {
+        let template = Template::parse("{author}/{identifier}/{title}.{ext}").unwrap();
+        let mut ebook = ebook("Dracula", "Bram Stoker");
+        ebook.identifier = String::new();
+        assert_eq!(
+            template.render(&ebook).unwrap(),
+            PathBuf::from("Bram Stoker/Dracula.epub")
+        );
+    }
+
+    #[test]
+    fn render_series() {
+        let template =
+            Template::parse("{author_sort}/{series}/{series_index:02} - {title}.{ext}").unwrap();
+        let mut ebook = ebook("Guards! Guards!", "Terry Pratchett");
+        ebook.creators[0].file_as = Some("Pratchett, Terry".to_owned());
+        assert_eq!(
+            template.render(&ebook).unwrap(),
+            PathBuf::from("Pratchett, Terry/Guards! Guards!.epub")
+        );
+
+        ebook.series = Some(Series::new("Discworld".to_owned(), Some(8.0)));
+        assert_eq!(
+            template.render(&ebook).unwrap(),
+            PathBuf::from("Pratchett, Terry/Discworld/08 - Guards! Guards!.epub")
+        );
+    }
+
+    #[test]
+    fn render_keeps_leading_and_trailing_punctuation() {
+        let template = Template::parse("{author}/{title} - {subtitle}.{ext}").unwrap();
+        for title in [".hack", "...And Then There Were None", "-ism", "Why?--"] {
+            assert_eq!(
+                template.render(&ebook(title, "Anonymous")).unwrap(),
+                Path::new("Anonymous").join(format!("{}.epub", title))
+            );
+        }
+    }
+
+    #[test]
+    fn render_missing_metadata() {
+        let template = Template::parse("{series}.{ext}").unwrap();
+        let result = template.render(&ebook("Dracula", "Bram Stoker"));
+        assert!(matches!(result, Err(Error::MissingMetadata(_))));
+
+        let template = Template::parse("{author}/{series}.{ext}").unwrap();
+        assert!(template.render(&ebook("Dracula", "Bram Stoker")).is_err());
+    }
+
+    #[test]
+    fn pad_numbers() {
+        assert_eq!(pad("3", Some(2)), "03");
+        assert_eq!(pad("2.5", Some(2)), "02.5");
+        assert_eq!(pad("123", Some(2)), "123");
+        assert_eq!(pad("abc", Some(2)), "abc");
+        assert_eq!(pad("3", None), "3");
+    }
+
+    #[test]
+    fn parse_invalid_templates() {
+        assert!(Template::parse("/{title}").is_err());
+        assert!(Template::parse("{author}//{title}").is_err());
+        assert!(Template::parse("../{title}").is_err());
+        assert!(Template::parse("{title").is_err());
+        assert!(Template::parse("title}").is_err());
+        assert!(Template::parse("{unknown}").is_err());
+        assert!(Template::parse("{title:x}").is_err());
+        assert!(Template::parse("{author}/{title}").is_err());
+        assert!(Template::parse("{author}/{title}.{ext}/cover").is_err());
+    }
+}
@@ -2,9 +2,12 @@ use std::error::Error;
 use std::fmt;
 use std::time::{Duration, Instant};
 
+use super::common;
 use super::config;
-use super::device;
+use super::device::{self, Device, Manifest};
 use super::list;
+use super::query::Query;
+use super::Ebook;
 
 struct UploadStats {
     uploaded: u32,
@@ -24,7 +27,116 @@ impl fmt::Display for UploadStats {
     }
 }
 
-pub fn run(config: &config::Config, dry_run: bool) -> Result<(), Box<dyn Error>> {
+/// Options controlling which books are uploaded, and to which devices.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Books to upload, given as identifiers of books and the words of a query. Every book in the
+    /// library is selected when empty.
+    pub selectors: Vec<String>,
+    /// Only upload to the device with this UID.
+    pub device: Option<String>,
+    /// Upload to this directory or named profile instead of the connected devices.
+    pub to: Option<String>,
+    /// Only upload the given number of most recently imported books out of the selection.
+    pub latest: Option<usize>,
+    /// Upload as many of the selected books as fit when a device is short on space, rather than
+    /// refusing to upload to it.
+    pub fit: bool,
+    /// Eject the devices once the books are uploaded.
+    pub eject: bool,
+    pub dry_run: bool,
+}
+
+/// Returns the ebooks selected by `selectors`. Selectors which are the identifier of a book select
+/// that book, and the rest are joined with spaces and parsed as a single query, like the
+/// arguments of `libri list`. Every ebook is selected when there are no selectors. When `latest`
+/// is set, only that many of the most recently imported ebooks are kept.
+pub(crate) fn select_ebooks(
+    ebooks: Vec<Ebook>,
+    selectors: &[String],
+    latest: Option<usize>,
+) -> Result<Vec<Ebook>, Box<dyn Error>> {
+    let (identifiers, words): (Vec<&str>, Vec<&str>) = selectors
+        .iter()
+        .map(String::as_str)
+        .partition(|selector| ebooks.iter().any(|ebook| ebook.identifier == *selector));
+    let query = if words.is_empty() {
+        None
+    } else {
+        let query = Query::parse(&words.join(" ")).map_err(|e| {
+            // A mistyped identifier would otherwise be reported as an invalid query field.
+            match words.iter().find(|word| Query::parse(word).is_err()) {
+                Some(word) if word.contains(':') => format!(
+                    "no book has the identifier '{}', and it is not a valid query: {}",
+                    word, e
+                ),
+                _ => e.to_string(),
+            }
+        })?;
+        Some(query)
+    };
+    let mut ebooks: Vec<Ebook> = ebooks
+        .into_iter()
+        .filter(|ebook| {
+            selectors.is_empty()
+                || identifiers.contains(&ebook.identifier.as_str())
+                || query.as_ref().is_some_and(|query| query.matches(ebook))
+        })
+        .collect();
+
+    if let Some(latest) = latest {
+        ebooks.sort_by_key(|ebook| std::cmp::Reverse(ebook.imported));
+        ebooks.truncate(latest);
+    }
+    Ok(ebooks)
+}
+
+/// Returns true if the ebook is already on the device, either where the manifest of the device
+/// says it was uploaded to or where it would be uploaded to now.
+fn on_device(ebook: &Ebook, device: &Device, config: &config::Config, manifest: &Manifest) -> bool {
+    manifest
+        .get(&ebook.identifier)
+        .is_some_and(|entry| manifest.path_of(entry).exists())
+        || device
+            .destination(ebook, config)
+            .is_ok_and(|destination| destination.exists())
+}
+
+/// The percentage added to the size of a book converted to KEPUB on upload. The conversion wraps
+/// every sentence in a span, so the converted book takes up more space than the original, though
+/// compression keeps the difference small.
+const KEPUB_MARGIN: u64 = 10;
+
+/// Estimates the space the ebook takes up on the device once uploaded.
+fn upload_size(ebook: &Ebook, device: &Device, config: &config::Config) -> u64 {
+    let converted = !device::books::is_kepub(&ebook.path)
+        && device
+            .destination(ebook, config)
+            .is_ok_and(|destination| device::books::is_kepub(&destination));
+    if converted {
+        ebook.size + ebook.size * KEPUB_MARGIN / 100
+    } else {
+        ebook.size
+    }
+}
+
+/// Returns the ebooks out of `pending`, given along with their upload sizes, which don't fit in
+/// `free` bytes. Books are kept in order for as long as they fit, and smaller books later on can
+/// still take up the remaining space.
+fn over_capacity<'a>(pending: &[(&'a Ebook, u64)], free: u64) -> Vec<&'a Ebook> {
+    let mut used = 0;
+    let mut left_out = Vec::new();
+    for &(ebook, size) in pending {
+        if used + size <= free {
+            used += size;
+        } else {
+            left_out.push(ebook);
+        }
+    }
+    left_out
+}
+
+pub fn run(config: &config::Config, options: &Options) -> Result<(), Box<dyn Error>> {
     let mut stats = UploadStats {
         uploaded: 0,
         skipped: 0,
@@ -32,26 +144,80 @@ pub fn run(config: &config::Config, dry_run: bool) -> Result<(), Box<dyn Error>>
     };
     let start = Instant::now();
 
-    let available_devices = device::available_devices()?;
-    // TODO: Filter devices based on user predicates
-    if available_devices.len() == 0 {
+    // FIXME: Modules are starting to become connected... perhaps list::get_ebooks should be moved
+    // to the common module in the future.
+    //
+    // Books are selected first, so mistakes in the selectors are reported before looking for
+    // devices.
+    let ebooks = select_ebooks(
+        list::get_ebooks(&config.library)?,
+        &options.selectors,
+        options.latest,
+    )?;
+    let available_devices = match (&options.to, &options.device) {
+        (Some(_), Some(_)) => return Err("--to and --device cannot be used together".into()),
+        (Some(target), None) => vec![device::manual_target(config, target)?],
+        (None, Some(uid)) => vec![device::find_device(config, uid)?],
+        (None, None) => device::available_devices(config)?,
+    };
+    if available_devices.is_empty() {
         println!("no devices available");
         return Ok(());
     }
-    // FIXME: Modules are starting to become connected... perhaps list::get_ebooks should be moved to the
-    // common module in the future.
-    let ebooks = list::get_ebooks(&config.library)?;
-    // TODO: Filter ebooks based on user predicates
-    if ebooks.len() == 0 {
+    if ebooks.is_empty() {
         println!("no ebooks selected");
         return Ok(());
     }
-    available_devices.iter().for_each(|device| {
+    for device in &available_devices {
         println!("{}", device.name());
-        ebooks.iter().for_each(|ebook| {
-            match device.upload_ebook(&ebook, &config.library, dry_run) {
-                Ok(_) => {
+        // Record the uploaded books so `libri device sync` can manage them later.
+        let mut manifest = device.manifest()?;
+        let mut uploads: Vec<&Ebook> = ebooks.iter().collect();
+        // Check the books fit up front, rather than running out of space halfway through.
+        match device.capacity() {
+            Ok(capacity) => {
+                let pending: Vec<(&Ebook, u64)> = ebooks
+                    .iter()
+                    .filter(|ebook| !on_device(ebook, device, config, &manifest))
+                    .map(|ebook| (ebook, upload_size(ebook, device, config)))
+                    .collect();
+                let needed: u64 = pending.iter().map(|(_, size)| size).sum();
+                if needed > capacity.free {
+                    let message = format!(
+                        "not enough space on device; {} books need {} but only {} is free",
+                        pending.len(),
+                        common::format_size(needed),
+                        common::format_size(capacity.free)
+                    );
+                    if !options.fit {
+                        stats.skipped += ebooks.len() as u32;
+                        println!(
+                            "skipping device -- {} (use --fit to upload as many as fit)",
+                            message
+                        );
+                        println!();
+                        continue;
+                    }
+                    println!("{}", message);
+                    let left_out = over_capacity(&pending, capacity.free);
+                    uploads
+                        .retain(|ebook| !left_out.iter().any(|other| std::ptr::eq(*ebook, *other)));
+                    for ebook in left_out {
+                        stats.skipped += 1;
+                        println!(
+                            "skipping \"{}\" -- not enough space on device",
+                            &ebook.title
+                        );
+                    }
+                }
+            }
+            Err(e) => eprintln!("warning: can't check the free space on the device: {}", e),
+        }
+        uploads.into_iter().for_each(|ebook| {
+            match device.upload_ebook(ebook, config, options.dry_run) {
+                Ok(path) => {
                     stats.uploaded += 1;
+                    manifest.insert(&ebook.identifier, &path);
                     println!("uploaded \"{}\"", &ebook.title);
                 }
                 Err(e) => {
@@ -60,13 +226,167 @@ pub fn run(config: &config::Config, dry_run: bool) -> Result<(), Box<dyn Error>>
                 }
             }
         });
+        if !options.dry_run {
+            manifest.save()?;
+        }
         println!();
-    });
+    }
     stats.elapsed = start.elapsed();
     print!("{}", stats);
-    if dry_run {
+    if options.dry_run {
         print!("; dry run");
     }
     println!();
+
+    if options.eject && !options.dry_run {
+        // Eject every device before reporting failures, so one stuck device doesn't keep the
+        // others mounted.
+        let mut failures = Vec::new();
+        for device in &available_devices {
+            // Directories given with --to may not be a device of their own.
+            if !device::eject::is_mount_point(device.mount_dir()) {
+                println!(
+                    "not ejecting '{}' -- not a mount point",
+                    device.mount_dir().display()
+                );
+                continue;
+            }
+            if let Err(e) = device::eject::eject(device, &mut device::eject::SystemRunner) {
+                failures.push(e.to_string());
+            }
+        }
+        if !failures.is_empty() {
+            return Err(failures.join("\n").into());
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::DeviceConfig;
+    use crate::device::kobo::Kobo;
+    use crate::device::registry::{self, KOBO_VENDOR_ID};
+    use crate::fixtures::EbookBuilder;
+
+    fn ebooks() -> Vec<Ebook> {
+        [
+            ("urn:uuid:1", "Emma", "Jane Austen", 1),
+            ("urn:uuid:2", "Persuasion", "Jane Austen", 3),
+            ("urn:uuid:3", "Dracula", "Bram Stoker", 2),
+        ]
+        .iter()
+        .map(|(identifier, title, author, day)| {
+            EbookBuilder::new(title, author)
+                .identifier(identifier)
+                .imported(2024, 1, *day)
+                .build()
+        })
+        .collect()
+    }
+
+    fn titles(ebooks: &[Ebook]) -> Vec<&str> {
+        ebooks.iter().map(|ebook| ebook.title()).collect()
+    }
+
+    fn titles_of<'a>(ebooks: &[&'a Ebook]) -> Vec<&'a str> {
+        ebooks.iter().map(|ebook| ebook.title()).collect()
+    }
+
+    #[test]
+    fn select_by_identifier_and_query() {
+        let selectors = vec!["urn:uuid:3".to_owned(), "title:emma".to_owned()];
+        let selected = select_ebooks(ebooks(), &selectors, None).unwrap();
+        assert_eq!(titles(&selected), ["Emma", "Dracula"]);
+    }
+
+    #[test]
+    fn select_latest() {
+        let selected = select_ebooks(ebooks(), &[], Some(2)).unwrap();
+        assert_eq!(titles(&selected), ["Persuasion", "Dracula"]);
+
+        let selectors = vec!["austen".to_owned()];
+        let selected = select_ebooks(ebooks(), &selectors, Some(1)).unwrap();
+        assert_eq!(titles(&selected), ["Persuasion"]);
+    }
+
+    #[test]
+    fn books_copied_by_hand_are_on_device() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let config = config::Config::default();
+        let device = device::manual_target(&config, dir.path().to_str().unwrap()).unwrap();
+        let manifest = device.manifest().unwrap();
+        let ebook = &ebooks()[0];
+        assert!(!on_device(ebook, &device, &config, &manifest));
+
+        // The book is missing from the manifest, but already at its destination.
+        let destination = dir.path().join("Jane Austen/Emma/Emma.epub");
+        fs::create_dir_all(destination.parent().unwrap()).unwrap();
+        fs::write(&destination, "emma").unwrap();
+        assert!(on_device(ebook, &device, &config, &manifest));
+    }
+
+    #[test]
+    fn leave_out_books_over_capacity() {
+        let mut ebooks = ebooks();
+        for (ebook, size) in ebooks.iter_mut().zip([400, 700, 300]) {
+            ebook.size = size;
+        }
+        let pending: Vec<(&Ebook, u64)> = ebooks.iter().map(|ebook| (ebook, ebook.size)).collect();
+        assert_eq!(titles_of(&over_capacity(&pending, 1000)), ["Persuasion"]);
+        assert!(over_capacity(&pending, 1400).is_empty());
+        assert_eq!(over_capacity(&pending, 100).len(), 3);
+    }
+
+    #[test]
+    fn kepub_conversions_need_extra_space() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let device = Device::new(
+            "Libra 2".to_owned(),
+            "Kobo".to_owned(),
+            Box::new(Kobo::new(
+                dir.path().to_path_buf(),
+                registry::find(KOBO_VENDOR_ID, 0x4234).unwrap(),
+            )),
+            None,
+        );
+        let mut config = config::Config::default();
+        let mut ebook = ebooks().remove(0);
+        ebook.size = 1000;
+        assert_eq!(upload_size(&ebook, &device, &config), 1000);
+
+        config.devices.insert(
+            device.uid().to_owned(),
+            DeviceConfig {
+                kepub: true,
+                ..DeviceConfig::default()
+            },
+        );
+        assert_eq!(upload_size(&ebook, &device, &config), 1100);
+    }
+
+    #[test]
+    fn select_with_multiword_query() {
+        let selectors: Vec<String> = ["author:austen", "AND", "title:emma"]
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        let selected = select_ebooks(ebooks(), &selectors, None).unwrap();
+        assert_eq!(titles(&selected), ["Emma"]);
+    }
+
+    #[test]
+    fn select_invalid_query() {
+        let selectors = vec!["colour:red".to_owned()];
+        assert!(select_ebooks(ebooks(), &selectors, None).is_err());
+
+        let selectors = vec!["urn:uuid:4".to_owned()];
+        let error = select_ebooks(ebooks(), &selectors, None).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("no book has the identifier 'urn:uuid:4'"));
+    }
+}
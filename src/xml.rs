@@ -1,71 +1,173 @@
+//! A minimal XML document tree.
+//!
+//! Elements can be looked up with simple paths such as `package/metadata/dc:title`, where each
+//! component names a child of the previous element and `*` matches any element. Unprefixed
+//! components match elements in any namespace, while prefixed components match elements in the
+//! namespace the prefix stands for in [`PREFIXES`], regardless of the prefix used by the document.
+
 use std::collections::HashMap;
 
-use xml::reader::{self, XmlEvent};
+use xml::reader::{self, EventReader, XmlEvent};
+
+/// Prefixes which can be used in paths, mapped to the namespace they stand for.
+const PREFIXES: &[(&str, &str)] = &[
+    ("dc", "http://purl.org/dc/elements/1.1/"),
+    ("opf", "http://www.idpf.org/2007/opf"),
+    (
+        "container",
+        "urn:oasis:names:tc:opendocument:xmlns:container",
+    ),
+    ("xhtml", "http://www.w3.org/1999/xhtml"),
+];
 
 #[derive(PartialEq, Debug)]
 pub struct XmlDocument {
-    // TODO: Other metadata
     pub encoding: String,
-    pub elements: Vec<XmlElement>,
+    pub root: XmlElement,
 }
 
 impl XmlDocument {
-    fn new() -> XmlDocument {
-        XmlDocument {
-            encoding: String::new(),
-            elements: Vec::new(),
+    /// Returns the first element matching `path`, whose first component matches the root element.
+    pub fn find(&self, path: &str) -> Option<&XmlElement> {
+        self.find_all(path).into_iter().next()
+    }
+
+    /// Returns every element matching `path`, whose first component matches the root element.
+    pub fn find_all(&self, path: &str) -> Vec<&XmlElement> {
+        let (first, rest) = match path.split_once('/') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (path, None),
+        };
+        if !self.root.matches(first) {
+            return Vec::new();
+        }
+        match rest {
+            Some(rest) => self.root.find_all(rest),
+            None => vec![&self.root],
         }
     }
 }
 
 #[derive(PartialEq, Debug)]
 pub struct XmlElement {
+    /// The namespace URI of the element.
+    pub namespace: Option<String>,
     pub prefix: Option<String>,
     pub tag: String,
+    /// The attributes of the element, keyed by their local name.
     pub attributes: HashMap<String, String>,
+    /// The text of the element and its descendants, in document order. CDATA sections are
+    /// included verbatim.
     pub content: String,
+    pub children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    /// Returns the first descendant matching `path`, relative to this element.
+    pub fn find(&self, path: &str) -> Option<&XmlElement> {
+        self.find_all(path).into_iter().next()
+    }
+
+    /// Returns every descendant matching `path`, relative to this element, in document order.
+    pub fn find_all(&self, path: &str) -> Vec<&XmlElement> {
+        let mut elements = vec![self];
+        for component in path.split('/') {
+            elements = elements
+                .into_iter()
+                .flat_map(|element| element.children.iter())
+                .filter(|element| element.matches(component))
+                .collect();
+        }
+        elements
+    }
+
+    /// Returns every descendant of this element, in document order.
+    pub fn descendants(&self) -> Vec<&XmlElement> {
+        let mut descendants = Vec::new();
+        for child in &self.children {
+            descendants.push(child);
+            descendants.extend(child.descendants());
+        }
+        descendants
+    }
+
+    /// Returns the value of the attribute with the given local name.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    /// Returns the text of the element with surrounding whitespace removed.
+    pub fn text(&self) -> &str {
+        self.content.trim()
+    }
+
+    /// Returns whether the element matches a single path component.
+    fn matches(&self, component: &str) -> bool {
+        match component.split_once(':') {
+            Some((prefix, tag)) => {
+                let namespace = PREFIXES
+                    .iter()
+                    .find(|(known, _)| *known == prefix)
+                    .map(|(_, namespace)| *namespace);
+                namespace.is_some()
+                    && self.namespace.as_deref() == namespace
+                    && (tag == "*" || self.tag == tag)
+            }
+            None => component == "*" || self.tag == component,
+        }
+    }
 }
 
+/// Parses an XML document into a tree of elements.
 pub fn parse(data: &str) -> reader::Result<XmlDocument> {
-    let mut document = XmlDocument::new();
-
-    for event in xml::reader::EventReader::from_str(data) {
-        if let Ok(ev) = event {
-            match ev {
-                XmlEvent::StartDocument {
-                    version: _,
-                    encoding,
-                    standalone: _,
-                } => {
-                    document.encoding = encoding;
-                }
-                XmlEvent::StartElement {
-                    name,
-                    attributes,
-                    namespace: _,
-                } => {
-                    let mut attrs = HashMap::new();
-                    for attr in attributes {
-                        attrs.insert(attr.name.local_name, attr.value);
+    let mut encoding = String::new();
+    // The elements which have been opened but not yet closed.
+    let mut open: Vec<XmlElement> = Vec::new();
+    let mut root = None;
+
+    for event in EventReader::from_str(data) {
+        match event? {
+            XmlEvent::StartDocument { encoding: e, .. } => encoding = e,
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                open.push(XmlElement {
+                    namespace: name.namespace,
+                    prefix: name.prefix,
+                    tag: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    content: String::new(),
+                    children: Vec::new(),
+                });
+            }
+            XmlEvent::EndElement { .. } => {
+                // The reader guarantees elements are balanced.
+                let element = open.pop().unwrap();
+                match open.last_mut() {
+                    Some(parent) => {
+                        parent.content.push_str(&element.content);
+                        parent.children.push(element);
                     }
-                    document.elements.push(XmlElement {
-                        prefix: name.prefix,
-                        tag: name.local_name,
-                        attributes: attrs,
-                        content: String::new(),
-                    });
+                    None => root = Some(element),
                 }
-                XmlEvent::Characters(c) => {
-                    if let Some(element) = document.elements.last_mut() {
-                        element.content = c;
-                    }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text) => {
+                if let Some(element) = open.last_mut() {
+                    element.content.push_str(&text);
                 }
-                _ => (),
             }
+            _ => (),
         }
     }
 
-    Ok(document)
+    Ok(XmlDocument {
+        encoding,
+        // The reader reports an error for documents without a root element.
+        root: root.unwrap(),
+    })
 }
 
 #[cfg(test)]
@@ -80,15 +182,52 @@ mod tests {
         let mut attributes = HashMap::new();
         attributes.insert(String::from("attr"), String::from("value"));
         let document = xml::parse(xml).unwrap();
-        assert_eq!(1, document.elements.len());
         assert_eq!(
             xml::XmlElement {
+                namespace: None,
                 prefix: None,
                 tag: String::from("test"),
-                attributes: attributes,
+                attributes,
                 content: String::from("contents"),
+                children: Vec::new(),
             },
-            *document.elements.get(0).unwrap()
+            document.root
         );
     }
+
+    #[test]
+    fn parse_mixed_content() {
+        let xml = "<p>Some <b>bold</b> and <![CDATA[<raw>]]> text</p>";
+        let document = xml::parse(xml).unwrap();
+        assert_eq!(document.root.content, "Some bold and <raw> text");
+        assert_eq!(document.root.children.len(), 1);
+        assert_eq!(document.root.children[0].content, "bold");
+    }
+
+    #[test]
+    fn find_by_namespace() {
+        let xml = r#"<package xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:purl="http://purl.org/dc/elements/1.1/">
+    <purl:title>First</purl:title>
+    <title>Not Dublin Core</title>
+    <purl:title>Second</purl:title>
+  </metadata>
+</package>"#;
+        let document = xml::parse(xml).unwrap();
+        let titles: Vec<&str> = document
+            .find_all("package/metadata/dc:title")
+            .into_iter()
+            .map(|element| element.text())
+            .collect();
+        assert_eq!(titles, ["First", "Second"]);
+        assert!(document.find("opf:package/opf:metadata").is_some());
+        assert!(document.find("metadata").is_none());
+        assert_eq!(document.find("*/*/title").unwrap().text(), "First");
+    }
+
+    #[test]
+    fn parse_malformed_xml() {
+        assert!(xml::parse("<package><metadata></package>").is_err());
+        assert!(xml::parse("").is_err());
+    }
 }
@@ -1,3 +1,5 @@
+mod common;
+
 use std::error::Error;
 use std::process::Command;
 
@@ -13,9 +15,25 @@ fn config() -> Result<(), Box<dyn Error>> {
 
     let mut cmd = Command::cargo_bin("libri")?;
     cmd.arg("--config-dir").arg(dir_path).arg("list");
+    cmd.assert().failure().stderr(
+        predicate::str::contains("configuration directory")
+            .and(predicate::str::contains("does not exist")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn config_invalid_path_template() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let config = dir.child("config.ini");
+    config.write_str("path_template = {author}/{unknown}.{ext}")?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("config");
     cmd.assert()
         .failure()
-        .stderr(predicate::str::contains("assertion failed: dir.is_dir()"));
+        .stderr(predicate::str::contains("unknown field 'unknown'"));
 
     Ok(())
 }
@@ -47,11 +65,640 @@ fn list_empty_library() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn import_and_list() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    import.create_dir_all()?;
+    common::write_epub(&import.path().join("a.epub"), "id-a", "Title A", "Author A");
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("imported \"Title A\""));
+    library
+        .child("Author A/Title A/Title A.epub")
+        .assert(predicate::path::exists());
+    library
+        .child(".libri/catalog.json")
+        .assert(predicate::str::contains("id-a"));
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("previously imported"));
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("list");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Title A").and(predicate::str::contains("Author A")));
+
+    Ok(())
+}
+
+#[test]
+fn list_sort_and_filter() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    library.create_dir_all()?;
+    common::write_epub(
+        &library.path().join("a.epub"),
+        "id-a",
+        "Emma",
+        "Jane Austen",
+    );
+    common::write_epub(
+        &library.path().join("b.epub"),
+        "id-b",
+        "Persuasion",
+        "Jane Austen",
+    );
+    common::write_epub(
+        &library.path().join("c.epub"),
+        "id-c",
+        "Dracula",
+        "Bram Stoker",
+    );
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["list", "--sort", "title", "--reverse", "--author", "austen"])
+        .args(["--columns", "title,identifier"]);
+    cmd.assert().success().stdout(predicate::str::is_match(
+        "^Title +Identifier\n.*\nPersuasion +id-b\nEmma +id-a\n$",
+    )?);
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["list", "--sort", "price"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown sort key 'price'"));
+
+    Ok(())
+}
+
+#[test]
+fn list_machine_readable_formats() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    library.create_dir_all()?;
+    common::write_epub(
+        &library.path().join("a.epub"),
+        "id-a",
+        "Emma",
+        "Jane Austen",
+    );
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["list", "--format", "json"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let records: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(records[0]["identifier"], "id-a");
+    assert_eq!(records[0]["title"], "Emma");
+    assert_eq!(records[0]["creators"][0]["name"], "Jane Austen");
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["list", "--format", "csv"]);
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("identifier,title,subtitle,")
+            .and(predicate::str::contains("\nid-a,Emma,,Emma,Jane Austen,")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn list_query() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    library.create_dir_all()?;
+    common::write_epub(
+        &library.path().join("a.epub"),
+        "id-a",
+        "Emma",
+        "Jane Austen",
+    );
+    common::write_epub(
+        &library.path().join("b.epub"),
+        "id-b",
+        "Dracula",
+        "Bram Stoker",
+    );
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["list", "author:austen OR title:\"the lair\""]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Emma").and(predicate::str::contains("Dracula").not()));
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["list", "author:austen", "AND", "(title:emma"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "expected ')' at position 30\n  author:austen AND (title:emma\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn import_with_path_template() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    import.create_dir_all()?;
+    common::write_epub(&import.path().join("a.epub"), "id-a", "Title A", "Author A");
+    let config = dir.child("config.ini");
+    config.write_str(
+        format!(
+            "library = {}\npath_template = {{author}} - {{title}}.{{ext}}",
+            library.path().to_str().unwrap()
+        )
+        .as_str(),
+    )?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert().success();
+    library
+        .child("Author A - Title A.epub")
+        .assert(predicate::path::exists());
+
+    Ok(())
+}
+
+#[test]
+fn import_continues_after_failure() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    import.create_dir_all()?;
+    common::write_epub(&import.path().join("a.epub"), "id-a", "Title A", "Author A");
+    import.child("broken.epub").write_str("not an epub")?;
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("imported 1; skipped 0; failed 1"))
+        .stdout(predicate::str::contains(
+            "broken.epub: invalid ebook archive",
+        ));
+    library
+        .child("Author A/Title A/Title A.epub")
+        .assert(predicate::path::exists());
+
+    Ok(())
+}
+
+#[test]
+fn import_fail_fast() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    import.create_dir_all()?;
+    import.child("broken.epub").write_str("not an epub")?;
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg("--fail-fast")
+        .arg(import.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("finished in").not())
+        .stderr(predicate::str::contains("broken.epub"));
+
+    Ok(())
+}
+
+#[test]
+fn reorganize() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    library.create_dir_all()?;
+    let book_dir = library.child("Author A/Title A");
+    book_dir.create_dir_all()?;
+    common::write_epub(
+        &book_dir.path().join("Title A.epub"),
+        "id-a",
+        "Title A",
+        "Author A",
+    );
+    book_dir.child("cover.jpg").write_str("cover")?;
+    let config = dir.child("config.ini");
+    config.write_str(
+        format!(
+            "library = {}\npath_template = {{author}}/{{title}}.{{ext}}",
+            library.path().to_str().unwrap()
+        )
+        .as_str(),
+    )?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("reorganize")
+        .arg("--dry-run");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "moved \"Title A\" to 'Author A/Title A.epub'",
+        ))
+        .stdout(predicate::str::contains("dry run"));
+    book_dir
+        .child("Title A.epub")
+        .assert(predicate::path::exists());
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("reorganize");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("moved 1; unchanged 0; skipped 0"));
+    library
+        .child("Author A/Title A.epub")
+        .assert(predicate::path::exists());
+    library
+        .child("Author A/cover.jpg")
+        .assert(predicate::path::exists());
+    book_dir.assert(predicate::path::missing());
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("reorganize");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("moved 0; unchanged 1; skipped 0"));
+
+    Ok(())
+}
+
+#[test]
+fn reorganize_into_shared_directory() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    for title in ["Title A", "Title B"] {
+        let book_dir = library.child(format!("Author A/{}", title));
+        book_dir.create_dir_all()?;
+        common::write_epub(
+            &book_dir.path().join(format!("{}.epub", title)),
+            title,
+            title,
+            "Author A",
+        );
+        book_dir.child("cover.jpg").write_str(title)?;
+    }
+    // A book at the root of the library doesn't own the other files there.
+    common::write_epub(
+        &library.path().join("Title C.epub"),
+        "Title C",
+        "Title C",
+        "Author C",
+    );
+    library.child("notes.txt").write_str("notes")?;
+    let config = dir.child("config.ini");
+    config.write_str(
+        format!(
+            "library = {}\npath_template = {{author}}/{{ext}}/{{title}}.{{ext}}",
+            library.path().to_str().unwrap()
+        )
+        .as_str(),
+    )?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("reorganize");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("moved 3; unchanged 0; skipped 0"));
+    let books = library.child("Author A/epub");
+    books
+        .child("Title A.epub")
+        .assert(predicate::path::exists());
+    books
+        .child("Title B.epub")
+        .assert(predicate::path::exists());
+    // Covers are named after their books once the books share a directory.
+    books.child("Title A.cover.jpg").assert("Title A");
+    books.child("Title B.cover.jpg").assert("Title B");
+    books.child("cover.jpg").assert(predicate::path::missing());
+    library
+        .child("Author A/Title A")
+        .assert(predicate::path::missing());
+    library
+        .child("Author A/Title B")
+        .assert(predicate::path::missing());
+    library
+        .child("Author C/epub/Title C.epub")
+        .assert(predicate::path::exists());
+    library.child("notes.txt").assert(predicate::path::exists());
+
+    // Moving the books back into directories of their own restores the plain names.
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("reorganize");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("moved 3; unchanged 0; skipped 0"));
+    library
+        .child("Author A/Title A/cover.jpg")
+        .assert("Title A");
+    library
+        .child("Author A/Title B/cover.jpg")
+        .assert("Title B");
+    books.assert(predicate::path::missing());
+
+    Ok(())
+}
+
+#[test]
+fn reorganize_sidecar_conflict() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let book_dir = library.child("Title A");
+    book_dir.create_dir_all()?;
+    common::write_epub(
+        &book_dir.path().join("Title A.epub"),
+        "id-a",
+        "Title A",
+        "Author A",
+    );
+    book_dir.child("cover.jpg").write_str("new")?;
+    // A cover left behind by an earlier copy of the book.
+    library
+        .child("Author A/Title A/Title A.cover.jpg")
+        .write_str("old")?;
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("reorganize");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "skipping \"Title A\" -- 'Author A/Title A/Title A.cover.jpg' already exists",
+        ))
+        .stdout(predicate::str::contains("moved 0; unchanged 0; skipped 1"));
+    book_dir
+        .child("Title A.epub")
+        .assert(predicate::path::exists());
+    book_dir.child("cover.jpg").assert("new");
+
+    Ok(())
+}
+
+#[test]
+fn reorganize_dry_run_conflicts() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    library.create_dir_all()?;
+    common::write_epub(&library.path().join("a.epub"), "id-a", "Title", "Author");
+    common::write_epub(&library.path().join("b.epub"), "id-b", "Title", "Author");
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("reorganize")
+        .arg("--dry-run");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("already exists"))
+        .stdout(predicate::str::contains("moved 1; unchanged 0; skipped 1"));
+
+    Ok(())
+}
+
+#[test]
+fn reindex() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    common::write_epub(&dir.path().join("a.epub"), "id-a", "Title A", "Author A");
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", dir.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir").arg(dir.path()).arg("reindex");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("indexed 1;"));
+    dir.child(".libri/catalog.json")
+        .assert(predicate::str::contains("Title A"));
+
+    Ok(())
+}
+
+#[test]
+fn import_and_export_cover() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    import.create_dir_all()?;
+    common::write_epub_with_cover(
+        &import.path().join("a.epub"),
+        "id-a",
+        "Title A",
+        "Author A",
+        b"jpeg data",
+    );
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert().success();
+    library
+        .child("Author A/Title A/cover.jpg")
+        .assert("jpeg data");
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("cover")
+        .arg("--output")
+        .arg(dir.path())
+        .arg("title a");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("saved cover of \"Title A\""));
+    dir.child("Title A.jpg").assert("jpeg data");
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("cover")
+        .arg("--output")
+        .arg(dir.path())
+        .arg("title a");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Title A.jpg' already exists"));
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("cover")
+        .arg("missing");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no book matches 'missing'"));
+
+    Ok(())
+}
+
+#[test]
+fn import_with_broken_cover() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    import.create_dir_all()?;
+    // The manifest refers to a cover image missing from the archive.
+    common::write_epub_with_opf(
+        &import.path().join("a.epub"),
+        r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">id-a</dc:identifier>
+    <dc:title>Title A</dc:title>
+    <dc:creator>Author A</dc:creator>
+  </metadata>
+  <manifest>
+    <item id="cover" href="cover.jpg" media-type="image/jpeg" properties="cover-image"/>
+  </manifest>
+</package>"#,
+    );
+    let config = dir.child("config.ini");
+    config.write_str(format!("library = {}", library.path().to_str().unwrap()).as_str())?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("imported 1; skipped 0; failed 0"))
+        .stderr(predicate::str::contains(
+            "failed to save the cover of \"Title A\"",
+        ));
+    library
+        .child("Author A/Title A/Title A.epub")
+        .assert(predicate::path::exists());
+
+    Ok(())
+}
+
+#[test]
+fn upload_to_directory() -> Result<(), Box<dyn Error>> {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let library = dir.child("library");
+    let import = dir.child("import");
+    let reader = dir.child("reader");
+    import.create_dir_all()?;
+    reader.create_dir_all()?;
+    common::write_epub(&import.path().join("a.epub"), "id-a", "Title A", "Author A");
+    common::write_epub(&import.path().join("b.epub"), "id-b", "Title B", "Author B");
+    let config = dir.child("config.ini");
+    config.write_str(
+        format!(
+            "library = {}\n\n[device \"sdcard\"]\nmount_path = {}\nbook_dir = Books",
+            library.path().to_str().unwrap(),
+            reader.path().to_str().unwrap()
+        )
+        .as_str(),
+    )?;
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("import")
+        .arg(import.path());
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .arg("upload")
+        .arg("--to")
+        .arg(reader.path())
+        .arg("id-a");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("uploaded \"Title A\""));
+    reader
+        .child("Author A/Title A/Title A.epub")
+        .assert(predicate::path::exists());
+    reader
+        .child(".libri/manifest.json")
+        .assert(predicate::str::contains("id-a"));
+
+    // A plain directory is left alone when ejecting.
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["upload", "--to", "sdcard", "--eject", "id-b"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("not ejecting"));
+    reader
+        .child("Books/Author B/Title B/Title B.epub")
+        .assert(predicate::path::exists());
+
+    let mut cmd = Command::cargo_bin("libri")?;
+    cmd.arg("--config-dir")
+        .arg(dir.path())
+        .args(["upload", "--to", "missing"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("'missing' is not a directory"));
+
+    Ok(())
+}
+
 #[test]
 fn list_unknown_argument() -> Result<(), Box<dyn Error>> {
     let mut cmd = Command::cargo_bin("libri")?;
 
-    cmd.arg("list").arg("extra_argument");
+    cmd.arg("list").arg("--extra-argument");
     cmd.assert()
         .failure()
         .stderr(predicate::str::contains("unknown argument"));
@@ -97,6 +744,18 @@ fn list_help() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn reindex_help() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("libri")?;
+
+    cmd.arg("reindex").arg("--help");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("libri-reindex"));
+
+    Ok(())
+}
+
 #[test]
 fn import_help() -> Result<(), Box<dyn Error>> {
     let mut cmd = Command::cargo_bin("libri")?;
@@ -121,6 +780,18 @@ fn upload_help() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn cover_help() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("libri")?;
+
+    cmd.arg("cover").arg("--help");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("libri-cover"));
+
+    Ok(())
+}
+
 #[test]
 fn device_help() -> Result<(), Box<dyn Error>> {
     let mut cmd = Command::cargo_bin("libri")?;
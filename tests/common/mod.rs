@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use zip::write::{FileOptions, ZipWriter};
+
+/// Writes a minimal EPUB containing the given metadata to `path`.
+pub fn write_epub(path: &Path, identifier: &str, title: &str, author: &str) {
+    write_epub_with_opf(path, &opf(identifier, title, author, ""));
+}
+
+/// Writes a minimal EPUB containing the given metadata and a JPEG cover image to `path`.
+pub fn write_epub_with_cover(
+    path: &Path,
+    identifier: &str,
+    title: &str,
+    author: &str,
+    cover: &[u8],
+) {
+    let manifest = r#"<manifest>
+    <item id="cover" href="cover.jpg" media-type="image/jpeg" properties="cover-image"/>
+  </manifest>"#;
+    write_epub_with_files(
+        path,
+        &opf(identifier, title, author, manifest),
+        &[("OEBPS/cover.jpg", cover)],
+    );
+}
+
+/// Writes an EPUB whose package document is `opf` to `path`.
+pub fn write_epub_with_opf(path: &Path, opf: &str) {
+    write_epub_with_files(path, opf, &[]);
+}
+
+/// Writes an EPUB whose package document is `opf` to `path`, along with additional files given
+/// as pairs of paths inside the archive and contents.
+pub fn write_epub_with_files(path: &Path, opf: &str, files: &[(&str, &[u8])]) {
+    let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    let mut zip = ZipWriter::new(File::create(path).unwrap());
+    let options = FileOptions::default();
+    zip.start_file("mimetype", options).unwrap();
+    zip.write_all(b"application/epub+zip").unwrap();
+    zip.start_file("META-INF/container.xml", options).unwrap();
+    zip.write_all(container.as_bytes()).unwrap();
+    zip.start_file("OEBPS/content.opf", options).unwrap();
+    zip.write_all(opf.as_bytes()).unwrap();
+    for (name, contents) in files {
+        zip.start_file(*name, options).unwrap();
+        zip.write_all(contents).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn opf(identifier: &str, title: &str, author: &str, manifest: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:creator>{}</dc:creator>
+  </metadata>
+  {}
+</package>"#,
+        identifier, title, author, manifest
+    )
+}